@@ -0,0 +1,59 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Compare parsing a large file with no multi-line values,
+//! with and without the [ParsePolicy::never_multiline] hint.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use key_value_parser::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    policies::TrivialParsePolicy,
+    KVParser, ParserOutput,
+};
+
+/// Like [TrivialParsePolicy], but without the hint.
+#[derive(Debug, Default)]
+struct NoHintPolicy {}
+
+impl ParsePolicy for NoHintPolicy {
+    type State = ();
+
+    fn process_value<'a>(&self, state: &mut (), key: &str, value: &'a str) -> ProcessedValue<'a> {
+        TrivialParsePolicy {}.process_value(state, key, value)
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut (),
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        TrivialParsePolicy {}.process_continuation(state, key, continuation_line)
+    }
+}
+
+fn parse_all<P: ParsePolicy>(policy: P, input: &str) -> usize {
+    let mut parser = KVParser::new(policy);
+    input
+        .lines()
+        .filter_map(|line| parser.process_line(line).ok())
+        .count()
+}
+
+fn single_line(c: &mut Criterion) {
+    let input: String = (0..100_000)
+        .map(|i| format!("Key{}: some value for line {}\n", i % 50, i))
+        .collect();
+    let mut group = c.benchmark_group("single_line");
+    group.bench_function("never_multiline", |b| {
+        b.iter(|| parse_all(TrivialParsePolicy {}, black_box(&input)))
+    });
+    group.bench_function("no_hint", |b| {
+        b.iter(|| parse_all(NoHintPolicy {}, black_box(&input)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, single_line);
+criterion_main!(benches);
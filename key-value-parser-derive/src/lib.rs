@@ -0,0 +1,133 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Derive macros for the `key-value-parser` crate.
+//!
+//! Use these through the `derive` feature of `key-value-parser` rather than directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Fields, GenericArgument, LitStr, PathArguments, Type,
+};
+
+/// Derive `FromRecord` for a struct with named fields.
+///
+/// Each field is extracted from the value of the key with the same name as the field,
+/// or the key named by a `#[kv(key = "SomeKey")]` attribute.
+/// Fields of type `Option<T>` are optional, all others are required.
+/// Values are converted to the field type using `FromStr`.
+#[proc_macro_derive(FromRecord, attributes(kv))]
+pub fn derive_from_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_record(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// If this type is `Option<T>`, return `T`.
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first() {
+        Some(GenericArgument::Type(inner)) if args.args.len() == 1 => Some(inner),
+        _ => None,
+    }
+}
+
+/// Get the key named in a `#[kv(key = "...")]` attribute, if any.
+fn key_from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let mut key = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("kv")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                key = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported kv attribute, expected `key`"))
+            }
+        })?;
+    }
+    Ok(key)
+}
+
+fn expand_from_record(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    input,
+                    "FromRecord can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "FromRecord can only be derived for structs",
+            ))
+        }
+    };
+
+    let field_inits = fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.as_ref().expect("named fields have identifiers");
+            let key = key_from_attrs(&field.attrs)?.unwrap_or_else(|| ident.to_string());
+            let init = match option_inner_type(&field.ty) {
+                Some(inner) => quote! {
+                    match record.value_for_key(#key)? {
+                        ::core::option::Option::Some(value) => ::core::option::Option::Some(parse_value::<#inner>(#key, value)?),
+                        ::core::option::Option::None => ::core::option::Option::None,
+                    }
+                },
+                None => {
+                    let ty = &field.ty;
+                    quote! {
+                        parse_value::<#ty>(#key, record.value_for_required_key(#key)?)?
+                    }
+                }
+            };
+            Ok(quote! { #ident: #init })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::key_value_parser::record::FromRecord for #name #ty_generics #where_clause {
+            fn from_record(
+                record: &::key_value_parser::record::Record,
+            ) -> ::core::result::Result<Self, ::key_value_parser::record::RecordError> {
+                fn parse_value<T: ::core::str::FromStr>(
+                    key: &str,
+                    value: &str,
+                ) -> ::core::result::Result<T, ::key_value_parser::record::RecordError>
+                where
+                    T::Err: ::core::fmt::Display,
+                {
+                    value.parse::<T>().map_err(|e| {
+                        ::key_value_parser::record::RecordError::Message(::std::format!(
+                            "Could not parse value of field {}: {}",
+                            key, e
+                        ))
+                    })
+                }
+                ::core::result::Result::Ok(Self {
+                    #(#field_inits,)*
+                })
+            }
+        }
+    })
+}
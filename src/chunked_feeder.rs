@@ -0,0 +1,103 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Feed a [KVParser] with arbitrary chunks of bytes rather than whole lines, using [ChunkedFeeder]
+
+use crate::{parse_policy::ParsePolicy, KVParser, KeyValuePair, LineNumber, Output};
+
+/// Wraps a [KVParser], buffering partial lines across chunks of input bytes.
+///
+/// Lines are split on `\n`, and a trailing `\r` is removed from each line.
+/// Invalid UTF-8 is replaced with the replacement character.
+#[derive(Debug)]
+pub struct ChunkedFeeder<P: ParsePolicy> {
+    parser: KVParser<P>,
+    buffer: Vec<u8>,
+}
+
+fn process_bytes<P: ParsePolicy>(
+    parser: &mut KVParser<P>,
+    line: &[u8],
+    outputs: &mut Vec<LineNumber<Output<KeyValuePair>>>,
+) {
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    outputs.push(parser.process_line(&String::from_utf8_lossy(line)));
+    outputs.extend(core::iter::from_fn(|| parser.take_deferred_output()));
+}
+
+impl<P: ParsePolicy> ChunkedFeeder<P> {
+    /// Create a feeder wrapping a parser.
+    pub fn new(parser: KVParser<P>) -> Self {
+        Self {
+            parser,
+            buffer: vec![],
+        }
+    }
+
+    /// Pass a chunk of bytes to process.
+    ///
+    /// Returns the output of every line completed by this chunk.
+    /// Any partial line at the end of the chunk is kept until more bytes arrive.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<LineNumber<Output<KeyValuePair>>> {
+        self.buffer.extend_from_slice(bytes);
+        let mut outputs = vec![];
+        let mut start = 0;
+        while let Some(len) = self.buffer[start..].iter().position(|&b| b == b'\n') {
+            process_bytes(
+                &mut self.parser,
+                &self.buffer[start..start + len],
+                &mut outputs,
+            );
+            start += len + 1;
+        }
+        self.buffer.drain(..start);
+        outputs
+    }
+
+    /// Signal the end of input.
+    ///
+    /// Processes any partial line remaining, then ends any multi-line value in progress
+    /// with [KVParser::finish].
+    pub fn finish(&mut self) -> Vec<LineNumber<Output<KeyValuePair>>> {
+        let mut outputs = vec![];
+        if !self.buffer.is_empty() {
+            let line = core::mem::take(&mut self.buffer);
+            process_bytes(&mut self.parser, &line, &mut outputs);
+        }
+        self.parser.finish_each(|output| outputs.push(output));
+        outputs
+    }
+
+    /// Unwrap the inner parser, discarding any partial line.
+    pub fn into_inner(self) -> KVParser<P> {
+        self.parser
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ChunkedFeeder;
+    use crate::{policies::SPDXParsePolicy, KVParser, Output, ParserOutput};
+
+    #[test]
+    fn chunk_boundaries() {
+        let input = "key: välue\r\nkey2: <text>a\nb</text>\n\nkey3: <text>c";
+        for chunk_size in 1..input.len() {
+            let mut feeder = ChunkedFeeder::new(KVParser::<SPDXParsePolicy>::default());
+            let mut outputs = vec![];
+            for chunk in input.as_bytes().chunks(chunk_size) {
+                outputs.extend(feeder.feed(chunk));
+            }
+            outputs.extend(feeder.finish());
+            let line_numbers: Vec<_> = outputs.iter().map(|o| o.line_number()).collect();
+            assert_eq!(line_numbers, vec![1, 2, 3, 4, 5, 5]);
+            assert!(matches!(outputs[5].value(), Output::Error(_)));
+
+            let pairs: Vec<_> = outputs.into_iter().filter_map(|o| o.ok()).collect();
+            assert_eq!(pairs.len(), 2);
+            assert_eq!(pairs[0], ("key", "välue"));
+            assert_eq!(pairs[1], ("key2", "a\nb"));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Collect every problem found while parsing a document, using [KVParser::parse_str_diagnostics]
+
+use core::fmt::Display;
+
+use crate::{parse_policy::ParsePolicy, KVParser, KeyValuePair, LineNumber, Output, ParseError};
+
+/// The kind of problem described by a [ParseDiagnostic].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A line with no key, that was not part of a multi-line value.
+    KeylessLine,
+    /// A key with leading or trailing whitespace, or containing control characters.
+    InvalidKey,
+    /// A multi-line value that was still in progress at the end of input.
+    UnterminatedMultiline,
+    /// A key that is empty.
+    EmptyKey,
+}
+
+/// A problem found while parsing, with the line number where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    pub line: usize,
+    pub kind: DiagnosticKind,
+    pub message: String,
+}
+
+impl Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A whitespace style problem in a line with a key, found by
+/// [KVParser::with_whitespace_lint].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhitespaceIssue {
+    /// The line is indented with a mix of tabs and spaces.
+    MixedIndentation,
+    /// The key ends in whitespace, so the delimiter is preceded by whitespace.
+    WhitespaceBeforeDelimiter,
+}
+
+/// A whitespace style problem, with the line number where it was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WhitespaceLint {
+    pub line: usize,
+    pub issue: WhitespaceIssue,
+}
+
+/// Find the whitespace style problems in a line whose key, before any trimming, is `key`.
+pub(crate) fn lint_whitespace(line: &str, key: &str) -> impl Iterator<Item = WhitespaceIssue> {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let mixed = indent.contains(' ') && indent.contains('\t');
+    let before_delimiter = key.trim_end().len() != key.len();
+    IntoIterator::into_iter([
+        (mixed, WhitespaceIssue::MixedIndentation),
+        (before_delimiter, WhitespaceIssue::WhitespaceBeforeDelimiter),
+    ])
+    .filter(|(found, _)| *found)
+    .map(|(_, issue)| issue)
+}
+
+fn check_key(line: usize, key: &str) -> Option<ParseDiagnostic> {
+    if key.is_empty() {
+        Some(ParseDiagnostic {
+            line,
+            kind: DiagnosticKind::EmptyKey,
+            message: "Key is empty".to_string(),
+        })
+    } else if key.trim().len() != key.len() || key.chars().any(char::is_control) {
+        Some(ParseDiagnostic {
+            line,
+            kind: DiagnosticKind::InvalidKey,
+            message: format!(
+                "Key {:?} has surrounding whitespace or control characters",
+                key
+            ),
+        })
+    } else {
+        None
+    }
+}
+
+fn handle_output(
+    output: LineNumber<Output<KeyValuePair>>,
+    pairs: &mut Vec<LineNumber<KeyValuePair>>,
+    diagnostics: &mut Vec<ParseDiagnostic>,
+) {
+    let (line, output) = output.into_tuple();
+    match output {
+        Output::KeylessLine(v) | Output::Error(ParseError::UnexpectedKeylessLine(v)) => diagnostics
+            .push(ParseDiagnostic {
+                line,
+                kind: DiagnosticKind::KeylessLine,
+                message: format!("Line has no key: {:?}", v),
+            }),
+        Output::Error(ParseError::UnterminatedMultiline { key, value }) => {
+            diagnostics.push(ParseDiagnostic {
+                line,
+                kind: DiagnosticKind::UnterminatedMultiline,
+                message: format!("Multi-line value for key {:?} is not terminated", key),
+            });
+            diagnostics.extend(check_key(line, &key));
+            pairs.push(LineNumber::new(line, KeyValuePair { key, value }));
+        }
+        Output::Output(pair) => {
+            diagnostics.extend(check_key(line, &pair.key));
+            pairs.push(LineNumber::new(line, pair));
+        }
+        Output::EmptyLine | Output::Pending | Output::Comment(_) => {}
+    }
+}
+
+impl<P: ParsePolicy> KVParser<P> {
+    /// Parse a whole string, collecting the key: value pairs along with
+    /// a diagnostic for every problem found.
+    ///
+    /// Pairs with invalid or empty keys, and unterminated multi-line values,
+    /// are reported as diagnostics but still included in the pairs.
+    /// The end of input is handled by [KVParser::finish], so the policy decides
+    /// whether a multi-line value in progress is unterminated.
+    pub fn parse_str_diagnostics(
+        &mut self,
+        input: &str,
+    ) -> (Vec<LineNumber<KeyValuePair>>, Vec<ParseDiagnostic>) {
+        let mut pairs = vec![];
+        let mut diagnostics = vec![];
+        for line in input.lines() {
+            handle_output(self.process_line(line), &mut pairs, &mut diagnostics);
+            while let Some(output) = self.take_deferred_output() {
+                handle_output(output, &mut pairs, &mut diagnostics);
+            }
+        }
+        self.finish_each(|output| handle_output(output, &mut pairs, &mut diagnostics));
+        (pairs, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DiagnosticKind;
+    use crate::{
+        policies::{IndentScopePolicy, SPDXParsePolicy},
+        KVParser,
+    };
+
+    #[test]
+    fn diagnostics() {
+        let input = "key: value\nkeyless\n: empty\n key : value\nlong: <text>a\nb";
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let (pairs, diagnostics) = parser.parse_str_diagnostics(input);
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs[3].line_number(), 5);
+        assert_eq!(*pairs[3].value(), ("long", "a\nb"));
+
+        let summary: Vec<_> = diagnostics.iter().map(|d| (d.line, d.kind)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (2, DiagnosticKind::KeylessLine),
+                (3, DiagnosticKind::EmptyKey),
+                (4, DiagnosticKind::InvalidKey),
+                (5, DiagnosticKind::UnterminatedMultiline),
+            ]
+        );
+        assert_eq!(
+            diagnostics[3].to_string(),
+            "line 5: Multi-line value for key \"long\" is not terminated"
+        );
+    }
+
+    #[test]
+    fn diagnostics_finished_by_policy() {
+        let mut parser: KVParser<IndentScopePolicy> = KVParser::default();
+        let (pairs, diagnostics) = parser.parse_str_diagnostics("a:\n  x\nb:\n  y");
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].line_number(), 3);
+        assert_eq!(*pairs[1].value(), ("b", "y"));
+        assert!(diagnostics.is_empty());
+    }
+}
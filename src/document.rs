@@ -0,0 +1,126 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Parse streams of several documents, separated by a marker line, using [DocumentSplitter]
+
+use crate::{
+    parse_policy::ParsePolicy,
+    record::{Record, RecordParser},
+    record_emitter::RecordEmitter,
+    ParserOutput,
+};
+
+/// The records of one document in a stream, as produced by [DocumentSplitter].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Document {
+    records: Vec<Record>,
+}
+
+impl Document {
+    /// Get a shared borrow of the records.
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Extract the records.
+    pub fn into_records(self) -> Vec<Record> {
+        self.records
+    }
+}
+
+/// Wraps a [RecordParser], splitting its input into documents on a separator line,
+/// such as a form feed (`"\x0c"`) or `%%`.
+///
+/// A line is a separator if it equals the separator exactly,
+/// even within a multi-line value, which it ends.
+/// At each separator, any record in progress is finished and the document is returned.
+/// Documents with no records, such as before a leading separator, are skipped.
+#[derive(Debug)]
+pub struct DocumentSplitter<R, P: ParsePolicy> {
+    parser: RecordParser<R, P>,
+    separator: String,
+    records: Vec<Record>,
+}
+
+impl<R: RecordEmitter, P: ParsePolicy> DocumentSplitter<R, P> {
+    /// Wrap a record parser, splitting documents on lines equal to `separator`.
+    pub fn new(parser: RecordParser<R, P>, separator: impl Into<String>) -> Self {
+        Self {
+            parser,
+            separator: separator.into(),
+            records: vec![],
+        }
+    }
+
+    /// Pass a line to process, returning the document it ends, if any.
+    pub fn process_line(&mut self, line: &str) -> Option<Document> {
+        if line == self.separator {
+            self.take_document()
+        } else {
+            self.records.extend(self.parser.process_line(line).ok());
+            while let Some(output) = self.parser.take_deferred_output() {
+                self.records.extend(output.ok());
+            }
+            None
+        }
+    }
+
+    /// End the input, returning the final document, if any.
+    pub fn finish(&mut self) -> Option<Document> {
+        self.take_document()
+    }
+
+    fn take_document(&mut self) -> Option<Document> {
+        self.records.extend(self.parser.finish());
+        if self.records.is_empty() {
+            None
+        } else {
+            Some(Document {
+                records: core::mem::take(&mut self.records),
+            })
+        }
+    }
+
+    /// Parse a whole stream of documents from a string.
+    pub fn parse_str(mut self, input: &str) -> Vec<Document> {
+        let mut documents: Vec<Document> = input
+            .lines()
+            .filter_map(|line| self.process_line(line))
+            .collect();
+        documents.extend(self.finish());
+        documents
+    }
+
+    /// Unwrap the inner record parser.
+    pub fn into_inner(self) -> RecordParser<R, P> {
+        self.parser
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DocumentSplitter;
+    use crate::{
+        emitters::BlankLineRecordEmitter, policies::SPDXParsePolicy, record::RecordParser,
+    };
+
+    fn splitter(separator: &str) -> DocumentSplitter<BlankLineRecordEmitter, SPDXParsePolicy> {
+        DocumentSplitter::new(RecordParser::default(), separator)
+    }
+
+    #[test]
+    fn split_documents() {
+        let input = "a: 1\n\nb: 2\n%%\nc: <text>3\n%%\n%%\nd: 4";
+        let documents = splitter("%%").parse_str(input);
+        let record_counts: Vec<_> = documents.iter().map(|d| d.records().len()).collect();
+        assert_eq!(record_counts, vec![2, 1, 1]);
+        // The separator ends a multi-line value in progress.
+        assert_eq!(documents[1].records()[0].as_slice()[0], ("c", "3"));
+        assert_eq!(documents[2].records()[0].as_slice()[0], ("d", "4"));
+
+        let documents = splitter("\x0c").parse_str("\x0c\na: 1\n\x0c\nb: 2\n");
+        assert_eq!(documents.len(), 2);
+        assert!(splitter("%%").parse_str("%%\n\n").is_empty());
+    }
+}
@@ -3,14 +3,43 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 use crate::{record_emitter::RecordEmitter, KeyValuePair, Output};
 
+#[cfg(feature = "std")]
+use crate::{parse_policy::ParsePolicy, record::Record};
+
 /// A record emitter that ends/emits records on a blank line.
+///
+/// Optionally, a number of consecutive blank lines may be allowed within a record,
+/// in which case a record ends on the first blank line beyond that number.
 #[derive(Debug, Default)]
 pub struct BlankLineRecordEmitter {
     fields: Vec<KeyValuePair>,
+    blank_lines_within_record: usize,
+    blank_line_marker: Option<KeyValuePair>,
+    pending_blank_lines: usize,
 }
 
 impl BlankLineRecordEmitter {
+    /// Allow up to `max` consecutive blank lines within a record without ending it.
+    ///
+    /// By default, this is 0, so any blank line ends a record.
+    pub fn with_blank_lines_within_record(mut self, max: usize) -> Self {
+        self.blank_lines_within_record = max;
+        self
+    }
+
+    /// Add a copy of `marker`, such as a pair with an empty key and value,
+    /// to the record for each blank line allowed within it by
+    /// [BlankLineRecordEmitter::with_blank_lines_within_record],
+    /// preserving the structure of the record.
+    ///
+    /// Blank lines that end a record, or come before its first field, are not marked.
+    pub fn with_blank_line_marker(mut self, marker: KeyValuePair) -> Self {
+        self.blank_line_marker = Some(marker);
+        self
+    }
+
     fn try_take(&mut self) -> Output<Vec<KeyValuePair>> {
+        self.pending_blank_lines = 0;
         if self.fields.is_empty() {
             Output::EmptyLine
         } else {
@@ -25,10 +54,23 @@ impl RecordEmitter for BlankLineRecordEmitter {
         maybe_field: Output<KeyValuePair>,
     ) -> Output<Vec<KeyValuePair>> {
         match maybe_field {
+            Output::EmptyLine if self.fields.is_empty() => Output::EmptyLine,
+            Output::EmptyLine if self.pending_blank_lines < self.blank_lines_within_record => {
+                self.pending_blank_lines += 1;
+                Output::Pending
+            }
             Output::EmptyLine => self.try_take(),
             Output::Pending => Output::Pending,
             Output::KeylessLine(v) => Output::KeylessLine(v),
+            Output::Error(e) => Output::Error(e),
+            Output::Comment(v) => Output::Comment(v),
             Output::Output(v) => {
+                let blank_lines = core::mem::take(&mut self.pending_blank_lines);
+                if let Some(marker) = &self.blank_line_marker {
+                    for _ in 0..blank_lines {
+                        self.fields.push(marker.clone());
+                    }
+                }
                 self.fields.push(v);
                 Output::Pending
             }
@@ -38,4 +80,396 @@ impl RecordEmitter for BlankLineRecordEmitter {
     fn end_input(&mut self) -> Output<Vec<KeyValuePair>> {
         self.try_take()
     }
+
+    fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+}
+
+/// Which occurrence of a key [DedupRecordEmitter] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DedupMode {
+    /// Keep the first field with each key.
+    KeepFirst,
+    /// Keep the last field with each key.
+    #[default]
+    KeepLast,
+}
+
+/// A record emitter that ends records on a blank line, like [BlankLineRecordEmitter],
+/// but keeps only one field with each key in each record.
+///
+/// The kept fields remain in their original positions relative to each other.
+#[derive(Debug, Default)]
+pub struct DedupRecordEmitter {
+    inner: BlankLineRecordEmitter,
+    mode: DedupMode,
+}
+
+impl DedupRecordEmitter {
+    /// Create an emitter keeping the field chosen by `mode` for each key.
+    pub fn new(mode: DedupMode) -> Self {
+        Self {
+            inner: BlankLineRecordEmitter::default(),
+            mode,
+        }
+    }
+
+    fn dedup(&self, output: Output<Vec<KeyValuePair>>) -> Output<Vec<KeyValuePair>> {
+        output.map(|fields| {
+            let mut seen = std::collections::HashSet::new();
+            match self.mode {
+                DedupMode::KeepFirst => fields
+                    .into_iter()
+                    .filter(|pair| seen.insert(pair.key.clone()))
+                    .collect(),
+                DedupMode::KeepLast => {
+                    let mut kept: Vec<KeyValuePair> = fields
+                        .into_iter()
+                        .rev()
+                        .filter(|pair| seen.insert(pair.key.clone()))
+                        .collect();
+                    kept.reverse();
+                    kept
+                }
+            }
+        })
+    }
+}
+
+impl RecordEmitter for DedupRecordEmitter {
+    fn accumulate_output(
+        &mut self,
+        maybe_field: Output<KeyValuePair>,
+    ) -> Output<Vec<KeyValuePair>> {
+        let output = self.inner.accumulate_output(maybe_field);
+        self.dedup(output)
+    }
+
+    fn end_input(&mut self) -> Output<Vec<KeyValuePair>> {
+        let output = self.inner.end_input();
+        self.dedup(output)
+    }
+
+    /// The number of fields accumulated, before removing duplicates.
+    fn field_count(&self) -> usize {
+        self.inner.field_count()
+    }
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug)]
+enum RecordSender {
+    Unbounded(std::sync::mpsc::Sender<Vec<KeyValuePair>>),
+    Bounded(std::sync::mpsc::SyncSender<Vec<KeyValuePair>>),
+}
+
+/// A record emitter that ends records on a blank line, like [BlankLineRecordEmitter],
+/// but sends each completed record over a channel instead of returning it.
+///
+/// Completed records are reported as [Output::Pending].
+/// If the receiver has hung up, the record is returned as usual instead.
+/// With a bounded channel, sending blocks until the receiver has room.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ChannelRecordEmitter {
+    inner: BlankLineRecordEmitter,
+    sender: RecordSender,
+}
+
+#[cfg(feature = "std")]
+impl ChannelRecordEmitter {
+    /// Create an emitter sending records over an unbounded channel.
+    pub fn new(sender: std::sync::mpsc::Sender<Vec<KeyValuePair>>) -> Self {
+        Self {
+            inner: BlankLineRecordEmitter::default(),
+            sender: RecordSender::Unbounded(sender),
+        }
+    }
+
+    /// Create an emitter sending records over a bounded channel.
+    pub fn new_bounded(sender: std::sync::mpsc::SyncSender<Vec<KeyValuePair>>) -> Self {
+        Self {
+            inner: BlankLineRecordEmitter::default(),
+            sender: RecordSender::Bounded(sender),
+        }
+    }
+
+    fn send(&self, output: Output<Vec<KeyValuePair>>) -> Output<Vec<KeyValuePair>> {
+        match output {
+            Output::Output(fields) => {
+                let result = match &self.sender {
+                    RecordSender::Unbounded(sender) => sender.send(fields),
+                    RecordSender::Bounded(sender) => sender.send(fields),
+                };
+                match result {
+                    Ok(()) => Output::Pending,
+                    Err(std::sync::mpsc::SendError(fields)) => Output::Output(fields),
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl RecordEmitter for ChannelRecordEmitter {
+    fn accumulate_output(
+        &mut self,
+        maybe_field: Output<KeyValuePair>,
+    ) -> Output<Vec<KeyValuePair>> {
+        let output = self.inner.accumulate_output(maybe_field);
+        self.send(output)
+    }
+
+    fn end_input(&mut self) -> Output<Vec<KeyValuePair>> {
+        let output = self.inner.end_input();
+        self.send(output)
+    }
+
+    fn field_count(&self) -> usize {
+        self.inner.field_count()
+    }
+}
+
+/// Write records as `key{delim}value` lines, separating records with an empty line,
+/// so that they can be parsed again with [BlankLineRecordEmitter].
+///
+/// Values are wrapped with [ParsePolicy::wrap_value] so that `policy` reproduces them when parsing.
+/// Returns an error of kind [std::io::ErrorKind::InvalidInput] for a value that `policy` cannot reproduce,
+/// for a key that is empty or contains `delim` or a line break, or for a record with no fields,
+/// leaving the output incomplete.
+#[cfg(feature = "std")]
+pub fn write_records<W: std::io::Write, P: ParsePolicy>(
+    records: &[Record],
+    w: &mut W,
+    delim: &str,
+    policy: &P,
+) -> std::io::Result<()> {
+    let invalid = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg);
+    for (i, record) in records.iter().enumerate() {
+        if record.as_slice().is_empty() {
+            return Err(invalid(format!("record {} has no fields", i)));
+        }
+        if i > 0 {
+            writeln!(w)?;
+        }
+        for pair in record.as_slice() {
+            let key = &pair.key;
+            if key.is_empty() || key.contains(delim) || key.contains(['\n', '\r']) {
+                return Err(invalid(format!("the key {:?} cannot be written", key)));
+            }
+            let value = policy
+                .wrap_value(key, &pair.value)
+                .ok_or_else(|| invalid(format!("the value for key {:?} cannot be written", key)))?;
+            writeln!(w, "{}{}{}", pair.key, delim, value)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use std::{sync::mpsc, thread};
+
+    use proptest::prelude::*;
+
+    use super::{
+        write_records, BlankLineRecordEmitter, ChannelRecordEmitter, DedupMode, DedupRecordEmitter,
+    };
+    use crate::{
+        parse_policy::ParsePolicy,
+        policies::{SPDXEscapedTextPolicy, SPDXParsePolicy, TrivialParsePolicy},
+        record::{parse_records_str, Record, RecordParser},
+        record_emitter::RecordEmitter,
+        KVParser, KeyValuePair, Output, ParserOutput,
+    };
+
+    #[test]
+    fn channel_emitter() {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let producer = thread::spawn(move || {
+            let mut parser = RecordParser::new(
+                ChannelRecordEmitter::new_bounded(sender),
+                KVParser::<SPDXParsePolicy>::default(),
+            );
+            for i in 0..10 {
+                parser.process_line(&format!("index: {}", i));
+                parser.process_line("key: <text>multi");
+                parser.process_line("line</text>");
+                assert!(parser.process_line("").value().is_pending());
+            }
+            parser.process_line("last: record");
+            assert!(parser.end_input().is_pending());
+        });
+        let records: Vec<_> = receiver.iter().collect();
+        producer.join().unwrap();
+        assert_eq!(records.len(), 11);
+        for (i, record) in records.iter().take(10).enumerate() {
+            assert_eq!(record[0], ("index", i.to_string()));
+            assert_eq!(record[1], ("key", "multi\nline"));
+        }
+        assert_eq!(records[10][0], ("last", "record"));
+    }
+
+    #[test]
+    fn blank_lines_within_record() {
+        let marker = KeyValuePair {
+            key: String::new(),
+            value: String::new(),
+        };
+        let mut parser = RecordParser::new(
+            BlankLineRecordEmitter::default()
+                .with_blank_lines_within_record(1)
+                .with_blank_line_marker(marker.clone()),
+            KVParser::<SPDXParsePolicy>::default(),
+        );
+        let mut records = vec![];
+        for line in ["", "a: 1", "", "b: 2", "", "", "c: 3", "", "", ""] {
+            records.extend(parser.process_line(line).ok());
+        }
+        records.extend(parser.end_input().ok());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_slice(), [("a", "1"), ("", ""), ("b", "2")]);
+        assert_eq!(records[1].as_slice(), [("c", "3")]);
+
+        // Without a marker, the blank lines are just swallowed.
+        let mut emitter = BlankLineRecordEmitter::default().with_blank_lines_within_record(2);
+        emitter.accumulate_output(Output::Output(marker.clone()));
+        assert!(emitter.accumulate_output(Output::EmptyLine).is_pending());
+        assert!(emitter.accumulate_output(Output::EmptyLine).is_pending());
+        emitter.accumulate_output(Output::Output(marker));
+        assert_eq!(emitter.field_count(), 2);
+        assert!(emitter.accumulate_output(Output::EmptyLine).is_pending());
+        assert_eq!(emitter.end_input().ok().map(|fields| fields.len()), Some(2));
+    }
+
+    #[test]
+    fn dedup() {
+        let parse = |mode| {
+            let mut parser = RecordParser::new(
+                DedupRecordEmitter::new(mode),
+                KVParser::<SPDXParsePolicy>::default(),
+            );
+            for line in ["a: 1", "b: 2", "a: 3", "c: 4", "b: 5"] {
+                parser.process_line(line);
+            }
+            parser.end_input().ok().unwrap()
+        };
+        assert_eq!(
+            parse(DedupMode::KeepFirst).as_slice(),
+            [("a", "1"), ("b", "2"), ("c", "4")]
+        );
+        assert_eq!(
+            parse(DedupMode::KeepLast).as_slice(),
+            [("a", "3"), ("c", "4"), ("b", "5")]
+        );
+    }
+
+    #[test]
+    fn channel_hung_up() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let mut parser = RecordParser::new(
+            ChannelRecordEmitter::new(sender),
+            KVParser::<SPDXParsePolicy>::default(),
+        );
+        parser.process_line("key: value");
+        assert!(parser.process_line("").value().is_some());
+    }
+
+    #[test]
+    fn write_records_round_trip() {
+        let pair = |key: &str, value: &str| KeyValuePair {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let records = vec![
+            Record::new(vec![pair("a", "1"), pair("b", "multi\n\nline\n")]),
+            Record::new(vec![pair("c", " padded"), pair("d", "<text>")]),
+        ];
+        let mut out = vec![];
+        write_records(&records, &mut out, ": ", &SPDXParsePolicy {}).unwrap();
+        let written = String::from_utf8(out).unwrap();
+        assert_eq!(
+            written,
+            "a: 1\nb: <text>multi\n\nline\n</text>\n\nc: <text> padded</text>\nd: <text><text></text>\n"
+        );
+        assert_eq!(parse_records_str::<SPDXParsePolicy>(&written), records);
+
+        // Trailing whitespace is trimmed from all but the last line of a value.
+        let records = vec![Record::new(vec![pair("a", "x \ny")])];
+        let err = write_records(&records, &mut vec![], ": ", &SPDXParsePolicy {}).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        // Records that would not be parsed the same way again
+        for records in [
+            vec![Record::new(vec![pair("k", "x\ny: z")])],
+            vec![Record::new(vec![pair("k: j", "v")])],
+            vec![Record::new(vec![pair("", "v")])],
+            vec![Record::new(vec![pair("a", "1")]), Record::new(vec![])],
+        ] {
+            let err =
+                write_records(&records, &mut vec![], ": ", &TrivialParsePolicy {}).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
+    /// Values of up to a few lines of printable characters, including surrounding whitespace
+    /// and `<text>` tags, both plain and escaped.
+    fn value_strategy() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            "[ -~]{0,6}",
+            Just(" ".to_string()),
+            Just("<text>".to_string()),
+            Just("</text>".to_string()),
+            Just("<\\/text>".to_string()),
+            Just("k: v".to_string()),
+        ];
+        let line = proptest::collection::vec(piece, 0..4).prop_map(|pieces| pieces.concat());
+        proptest::collection::vec(line, 1..4).prop_map(|lines| lines.join("\n"))
+    }
+
+    fn record_strategy() -> impl Strategy<Value = Record> {
+        proptest::collection::vec(("[A-Za-z][A-Za-z0-9_]{0,8}", value_strategy()), 0..5).prop_map(
+            |fields| {
+                Record::new(
+                    fields
+                        .into_iter()
+                        .map(|(key, value)| KeyValuePair { key, value })
+                        .collect(),
+                )
+            },
+        )
+    }
+
+    /// Records, often next to empty records, which must not shift the boundaries of the others.
+    fn records_strategy() -> impl Strategy<Value = Vec<Record>> {
+        let record = prop_oneof![1 => Just(Record::default()), 3 => record_strategy()];
+        proptest::collection::vec(record, 0..5)
+    }
+
+    /// Write records with a policy, then check that they are parsed again,
+    /// unless the policy reports that it cannot reproduce one of the values.
+    fn round_trip<P: ParsePolicy + Default>(records: &[Record]) -> Result<(), TestCaseError> {
+        let mut out = vec![];
+        if let Err(e) = write_records(records, &mut out, ": ", &P::default()) {
+            prop_assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+            return Ok(());
+        }
+        let parsed = parse_records_str::<P>(&String::from_utf8(out).unwrap());
+        prop_assert_eq!(parsed.len(), records.len());
+        for (parsed, record) in parsed.iter().zip(records) {
+            prop_assert!(parsed.eq_unordered(record), "{:?} != {:?}", parsed, record);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn write_records_then_parse(records in records_strategy()) {
+            round_trip::<SPDXParsePolicy>(&records)?;
+            round_trip::<SPDXEscapedTextPolicy>(&records)?;
+        }
+    }
 }
@@ -5,15 +5,28 @@
 // #[cfg(feature = "async")]
 // pub mod async_functions;
 
+// Lets derived code refer to this crate by name in our own tests.
+#[cfg(test)]
+extern crate self as key_value_parser;
+
 #[cfg(feature = "std")]
 pub mod record;
 
+pub mod chunked_feeder;
+pub mod diagnostics;
+#[cfg(feature = "std")]
+pub mod document;
 pub mod emitters;
+pub mod line_endings;
 pub mod parse_policy;
 pub mod parsed_line;
 pub mod parser;
 pub mod policies;
 pub mod record_emitter;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod spdx;
 mod types;
 
 pub use parser::KVParser;
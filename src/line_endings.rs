@@ -0,0 +1,78 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Detect and normalize the line endings of a whole document, before parsing it.
+
+use std::borrow::Cow;
+
+/// The style of line endings used by a document, as found by [detect_line_ending].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Lines end with `\n` only.
+    Lf,
+    /// Lines end with `\r\n`.
+    CrLf,
+    /// Some lines end with `\n` and others with `\r\n`.
+    Mixed,
+}
+
+impl LineEnding {
+    /// The characters ending a line in this style, or `None` for [LineEnding::Mixed].
+    pub fn as_str(&self) -> Option<&'static str> {
+        match self {
+            LineEnding::Lf => Some("\n"),
+            LineEnding::CrLf => Some("\r\n"),
+            LineEnding::Mixed => None,
+        }
+    }
+}
+
+/// Find the style of line endings used by a document.
+///
+/// A document with no line endings at all is reported as [LineEnding::Lf].
+pub fn detect_line_ending(input: &str) -> LineEnding {
+    let newlines = input.matches('\n').count();
+    let crlfs = input.matches("\r\n").count();
+    if crlfs == 0 {
+        LineEnding::Lf
+    } else if crlfs == newlines {
+        LineEnding::CrLf
+    } else {
+        LineEnding::Mixed
+    }
+}
+
+/// Replace every `\r\n` with `\n`, only allocating if there are any.
+///
+/// A lone `\r` not followed by `\n` is left unchanged.
+pub fn normalize_line_endings(input: &str) -> Cow<'_, str> {
+    if input.contains("\r\n") {
+        Cow::Owned(input.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use super::{detect_line_ending, normalize_line_endings, LineEnding};
+
+    #[test]
+    fn line_endings() {
+        assert_eq!(detect_line_ending(""), LineEnding::Lf);
+        assert_eq!(detect_line_ending("a: b"), LineEnding::Lf);
+        assert_eq!(detect_line_ending("a: b\nc: d\n"), LineEnding::Lf);
+        assert_eq!(detect_line_ending("a: b\r\nc: d\r\n"), LineEnding::CrLf);
+        assert_eq!(detect_line_ending("a: b\r\nc: d\n"), LineEnding::Mixed);
+
+        assert!(matches!(
+            normalize_line_endings("a: b\nc: d\r"),
+            Cow::Borrowed("a: b\nc: d\r")
+        ));
+        assert_eq!(normalize_line_endings("a: b\r\nc: d\n"), "a: b\nc: d\n");
+        assert_eq!(LineEnding::CrLf.as_str(), Some("\r\n"));
+    }
+}
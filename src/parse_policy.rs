@@ -4,35 +4,75 @@
 
 //! Details that only affect those implementing a policy for [KVParser](crate::KVParser)
 
-use core::fmt::Debug;
+use core::{any::Any, fmt::Debug};
+use std::borrow::Cow;
+
+use crate::ParsedLine;
 
 /// Enum returned by a [ParsePolicy] when processing a value.
+///
+/// Values are usually borrowed from the input line, but a policy may
+/// return an owned value if it transforms the value.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ProcessedValue<'a> {
     /// Indicates that the provided value is complete and not continued on the following line.
     ///
     /// The data in this variant should have any multi-line decoration stripped.
-    CompleteValue(&'a str),
+    CompleteValue(Cow<'a, str>),
     /// Indicates that the provided value is not complete, and that
     /// additional lines should be processed before terminating this key: value pair.
     /// If there is a value in this variant, it will be added as the first line in the overall pair value.
     ///
     /// The data in this variant should have any multi-line decoration stripped.
-    StartOfMultiline(Option<&'a str>),
+    StartOfMultiline(Option<Cow<'a, str>>),
+    /// Like [ProcessedValue::CompleteValue], but only part of the value is used:
+    /// the remaining text, the second field of this variant, is then processed
+    /// as a line of its own.
+    ///
+    /// Useful for packed lines that contain a whole value and start the next, such as
+    /// `key: <text>value</text>key2: value2`.
+    CompleteValueAndReprocessRest(Cow<'a, str>, &'a str),
 }
 
 /// Enum returned by a [ParsePolicy] when processing a continuation line for a multi-line value.
+///
+/// As with [ProcessedValue], values may be borrowed or owned.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ProcessedContinuationValue<'a> {
     /// Indicates that the provided value is not complete, and that
     /// additional lines should be processed before terminating this key: value pair.
     /// If there is a value in this variant, it will be added as a line to the overall pair value.
     ///
     /// The data in this variant should have any multi-line decoration stripped.
-    ContinueMultiline(Option<&'a str>),
+    ContinueMultiline(Option<Cow<'a, str>>),
     /// Indicates that the provided value terminates the multi-line value.
     /// If there is a value in this variant, it will be added as a line to the overall pair value.
     ///
     /// The data in this variant should have any multi-line decoration stripped.
-    FinishMultiline(Option<&'a str>),
+    FinishMultiline(Option<Cow<'a, str>>),
+    /// Like [ProcessedContinuationValue::ContinueMultiline], but the value is appended
+    /// to the previous line of the overall pair value, with no separator,
+    /// rather than added as a new line.
+    ///
+    /// Useful for soft-wrapped values.
+    AppendToPrevious(Cow<'a, str>),
+    /// Like [ProcessedContinuationValue::FinishMultiline], but the value is appended
+    /// to the previous line of the overall pair value, with no separator,
+    /// rather than added as a new line.
+    FinishAppendToPrevious(Cow<'a, str>),
+    /// Indicates that the provided line is not part of the multi-line value at all,
+    /// for example because it looks like the start of a new key: value pair.
+    ///
+    /// The pending multi-line value is terminated without consuming this line,
+    /// and the line is then processed again as though no multi-line value were in progress.
+    AbortAndReprocess,
+    /// Like [ProcessedContinuationValue::FinishMultiline], but only part of the line is used:
+    /// the remaining text, the second field of this variant, is then processed
+    /// as a line of its own, as though no multi-line value were in progress.
+    ///
+    /// Useful for packed lines that end one value and start the next, such as
+    /// `end</text>key: <text>start`.
+    FinishAndReprocessRest(Option<Cow<'a, str>>, &'a str),
 }
 
 /// Implement this policy to customize how [KVParser](crate::KVParser) works,
@@ -40,19 +80,336 @@ pub enum ProcessedContinuationValue<'a> {
 ///
 /// Bundled policies are in [crate::policies]
 pub trait ParsePolicy: Debug {
+    /// State carried from one call of this policy to the next, such as the indentation
+    /// of the line that started a multi-line value.
+    ///
+    /// Each [KVParser](crate::KVParser) owns the state for its policy, starting from the default,
+    /// and passes it to [ParsePolicy::parse_line], [ParsePolicy::process_value],
+    /// [ParsePolicy::process_continuation] and [ParsePolicy::finish],
+    /// so one policy may be shared by several parsers.
+    /// The parser may also clone the state to try a line without changing it,
+    /// as in [KVParser::would_finish](crate::KVParser::would_finish).
+    /// Use `()` for a policy with no state.
+    type State: Debug + Default + Clone;
+
+    /// Called to split a line into a key and value, when no multi-line value is in progress.
+    ///
+    /// The default implementation splits on the first `: ` using [ParsedLine::from].
+    /// Override this for formats with a different delimiter or escaping rules.
+    fn parse_line(&self, _state: &mut Self::State, line: &str) -> ParsedLine {
+        ParsedLine::from(line)
+    }
+
+    /// Whether a line is a comment, when no multi-line value is in progress.
+    ///
+    /// Comment lines are returned by [KVParser](crate::KVParser) as [Output::Comment](crate::Output::Comment),
+    /// without being passed to the other methods of this policy.
+    /// The default implementation returns false.
+    fn is_comment(&self, _line: &str) -> bool {
+        false
+    }
+
+    /// A hint that [ParsePolicy::process_value] never returns [ProcessedValue::StartOfMultiline],
+    /// allowing [KVParser](crate::KVParser) to skip its handling of multi-line values.
+    ///
+    /// The default implementation returns false, which is always correct.
+    fn never_multiline(&self) -> bool {
+        false
+    }
+
+    /// Whether [KVParser](crate::KVParser) should trim whitespace from the start and end of keys,
+    /// before they are passed to the other methods of this policy.
+    ///
+    /// The default implementation returns false, leaving keys as split by [ParsePolicy::parse_line].
+    fn trim_keys(&self) -> bool {
+        false
+    }
+
+    /// Called when writing a value, for example by [write_records](crate::emitters::write_records),
+    /// to add any decoration needed for [ParsePolicy::process_value] and
+    /// [ParsePolicy::process_continuation] to reproduce it, such as for multi-line values.
+    ///
+    /// Returns `None` if this policy cannot reproduce the value however it is written.
+    /// The default implementation returns the value unchanged if it is a single line
+    /// that [ParsePolicy::process_value] completes as-is, such as without trimming it,
+    /// and `None` otherwise.
+    fn wrap_value<'a>(&self, key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        if value.contains(['\n', '\r']) {
+            return None;
+        }
+        match self.process_value(&mut Self::State::default(), key, value) {
+            ProcessedValue::CompleteValue(processed) if processed == value => {
+                Some(Cow::Borrowed(value))
+            }
+            _ => None,
+        }
+    }
+
     /// Called when a key and value are parsed.
     ///
-    /// Allows you to trim the value, as well as report
+    /// Allows you to trim or otherwise transform the value, as well as report
     /// that it is only the beginning of a multi-line value.
-    fn process_value<'a>(&self, key: &str, value: &'a str) -> ProcessedValue<'a>;
+    ///
+    /// Return [Cow::Borrowed] slices of `value` when possible, to avoid allocating.
+    fn process_value<'a>(
+        &self,
+        state: &mut Self::State,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a>;
     /// Called with each new line once [ParsePolicy::process_value] returns
     /// [ProcessedValue::StartOfMultiline].
     ///
-    /// Allows you to possibly trim or drop the line, and indicate
+    /// Allows you to possibly trim, transform, or drop the line, and indicate
     /// when the multi-line value has finished.
     fn process_continuation<'a>(
         &self,
+        state: &mut Self::State,
         key: &str,
         continuation_line: &'a str,
     ) -> ProcessedContinuationValue<'a>;
+
+    /// Called by [KVParser::finish](crate::KVParser::finish) at the end of input,
+    /// if a multi-line value is in progress, to decide how it ends.
+    ///
+    /// Return a finishing variant, such as [ProcessedContinuationValue::FinishMultiline],
+    /// for values that may legitimately end at the end of input.
+    /// The default implementation returns `None`, reporting the value as unterminated.
+    fn finish<'a>(&self, _state: &mut Self::State) -> Option<ProcessedContinuationValue<'a>> {
+        None
+    }
+}
+
+/// Implement this for a policy wrapping another, to override only the hooks it changes:
+/// [ParsePolicy] is implemented for every policy wrapper, and by default each hook
+/// calls the same hook of the inner policy.
+///
+/// The hooks are documented on [ParsePolicy]. The wrapper shares the state of the inner policy.
+pub trait PolicyWrapper: Debug {
+    /// The type of the wrapped policy.
+    type Inner: ParsePolicy + ?Sized;
+
+    /// The wrapped policy.
+    fn inner(&self) -> &Self::Inner;
+
+    /// See [ParsePolicy::parse_line].
+    fn parse_line(&self, state: &mut InnerState<Self>, line: &str) -> ParsedLine {
+        self.inner().parse_line(state, line)
+    }
+
+    /// See [ParsePolicy::is_comment].
+    fn is_comment(&self, line: &str) -> bool {
+        self.inner().is_comment(line)
+    }
+
+    /// See [ParsePolicy::never_multiline].
+    fn never_multiline(&self) -> bool {
+        self.inner().never_multiline()
+    }
+
+    /// See [ParsePolicy::trim_keys].
+    fn trim_keys(&self) -> bool {
+        self.inner().trim_keys()
+    }
+
+    /// See [ParsePolicy::wrap_value].
+    fn wrap_value<'a>(&self, key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        self.inner().wrap_value(key, value)
+    }
+
+    /// See [ParsePolicy::process_value].
+    fn process_value<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        self.inner().process_value(state, key, value)
+    }
+
+    /// See [ParsePolicy::process_continuation].
+    fn process_continuation<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        self.inner()
+            .process_continuation(state, key, continuation_line)
+    }
+
+    /// See [ParsePolicy::finish].
+    fn finish<'a>(&self, state: &mut InnerState<Self>) -> Option<ProcessedContinuationValue<'a>> {
+        self.inner().finish(state)
+    }
+}
+
+/// The state of the policy wrapped by a [PolicyWrapper].
+pub type InnerState<W> = <<W as PolicyWrapper>::Inner as ParsePolicy>::State;
+
+impl<W: PolicyWrapper> ParsePolicy for W {
+    type State = InnerState<W>;
+
+    fn parse_line(&self, state: &mut Self::State, line: &str) -> ParsedLine {
+        PolicyWrapper::parse_line(self, state, line)
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        PolicyWrapper::is_comment(self, line)
+    }
+
+    fn never_multiline(&self) -> bool {
+        PolicyWrapper::never_multiline(self)
+    }
+
+    fn trim_keys(&self) -> bool {
+        PolicyWrapper::trim_keys(self)
+    }
+
+    fn wrap_value<'a>(&self, key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        PolicyWrapper::wrap_value(self, key, value)
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut Self::State,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        PolicyWrapper::process_value(self, state, key, value)
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut Self::State,
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        PolicyWrapper::process_continuation(self, state, key, continuation_line)
+    }
+
+    fn finish<'a>(&self, state: &mut Self::State) -> Option<ProcessedContinuationValue<'a>> {
+        PolicyWrapper::finish(self, state)
+    }
+}
+
+/// Allows boxed policies, including `Box<dyn ParsePolicy<State = DynState>>`,
+/// to be used with [KVParser](crate::KVParser).
+impl<P: ParsePolicy + ?Sized> PolicyWrapper for Box<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        self
+    }
+}
+
+/// Object-safe helper for the state held by [DynState].
+trait AnyState: Any + Debug {
+    fn clone_box(&self) -> Box<dyn AnyState>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<S: Any + Debug + Clone> AnyState for S {
+    fn clone_box(&self) -> Box<dyn AnyState> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// The state of a [DynPolicy], whose type is only known at runtime.
+///
+/// It starts out empty, and holds the default state of the wrapped policy once first used.
+#[derive(Debug, Default)]
+pub struct DynState(Option<Box<dyn AnyState>>);
+
+impl Clone for DynState {
+    fn clone(&self) -> Self {
+        Self(self.0.as_ref().map(|state| (**state).clone_box()))
+    }
+}
+
+impl DynState {
+    /// Get the state as `S`, replacing it with the default if it holds anything else.
+    fn get<S: Any + Debug + Default + Clone>(&mut self) -> &mut S {
+        if !matches!(&self.0, Some(state) if (**state).as_any().is::<S>()) {
+            self.0 = Some(Box::new(S::default()));
+        }
+        self.0
+            .as_mut()
+            .and_then(|state| (**state).as_any_mut().downcast_mut())
+            .expect("state was just checked or replaced")
+    }
+}
+
+/// A policy wrapping another, with its state as a [DynState],
+/// so that policies with different types of state can be used as a [BoxedPolicy].
+#[derive(Debug, Default, Clone)]
+pub struct DynPolicy<P>(pub P);
+
+/// A boxed policy of any type, as created by [DynPolicy::boxed].
+pub type BoxedPolicy = Box<dyn ParsePolicy<State = DynState>>;
+
+impl<P: ParsePolicy + 'static> DynPolicy<P> {
+    /// Wrap and box a policy.
+    pub fn boxed(policy: P) -> BoxedPolicy {
+        Box::new(DynPolicy(policy))
+    }
+}
+
+impl<P: ParsePolicy> ParsePolicy for DynPolicy<P>
+where
+    P::State: 'static,
+{
+    type State = DynState;
+
+    fn parse_line(&self, state: &mut DynState, line: &str) -> ParsedLine {
+        self.0.parse_line(state.get(), line)
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        self.0.is_comment(line)
+    }
+
+    fn never_multiline(&self) -> bool {
+        self.0.never_multiline()
+    }
+
+    fn trim_keys(&self) -> bool {
+        self.0.trim_keys()
+    }
+
+    fn wrap_value<'a>(&self, key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        self.0.wrap_value(key, value)
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut DynState,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        self.0.process_value(state.get(), key, value)
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut DynState,
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        self.0
+            .process_continuation(state.get(), key, continuation_line)
+    }
+
+    fn finish<'a>(&self, state: &mut DynState) -> Option<ProcessedContinuationValue<'a>> {
+        self.0.finish(state.get())
+    }
 }
@@ -6,26 +6,58 @@
 
 use crate::{KeyValuePair, ParsedLine};
 
-const DELIM: &str = ": ";
+pub(crate) const DELIM: &str = ": ";
 
-impl From<&str> for ParsedLine {
-    fn from(line: &str) -> Self {
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
+impl ParsedLine {
+    /// Split a line into a key and value on the first occurrence of `delim`,
+    /// which may be any string, including non-ASCII characters such as `→`.
+    ///
+    /// Lines that are empty or whitespace-only are [ParsedLine::EmptyLine],
+    /// and lines without the delimiter are [ParsedLine::KeylessLine].
+    /// Useful in [ParsePolicy::parse_line](crate::parse_policy::ParsePolicy::parse_line)
+    /// for formats with a different delimiter.
+    pub fn split_on(line: &str, delim: &str) -> Self {
+        if line.trim().is_empty() {
             ParsedLine::EmptyLine
         } else {
-            match line.match_indices(DELIM).next() {
-                Some((delim, _)) => {
-                    let (k, v) = line.split_at(delim);
-                    let v = &v[DELIM.len()..];
-
-                    ParsedLine::Pair(KeyValuePair {
-                        key: String::from(k),
-                        value: String::from(v),
-                    })
-                }
+            match line.split_once(delim) {
+                Some((k, v)) => ParsedLine::Pair(KeyValuePair {
+                    key: String::from(k),
+                    value: String::from(v),
+                }),
                 None => ParsedLine::KeylessLine(line.to_string()),
             }
         }
     }
 }
+
+impl From<&str> for ParsedLine {
+    fn from(line: &str) -> Self {
+        ParsedLine::split_on(line, DELIM)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{KeyValuePair, ParsedLine};
+
+    fn pair(key: &str, value: &str) -> ParsedLine {
+        ParsedLine::Pair(KeyValuePair {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    #[test]
+    fn multi_byte_delimiters() {
+        assert_eq!(ParsedLine::from("ké: välue"), pair("ké", "välue"));
+        assert_eq!(ParsedLine::split_on("a→b→c", "→"), pair("a", "b→c"));
+        assert_eq!(ParsedLine::split_on("名前：値", "："), pair("名前", "値"));
+        assert_eq!(ParsedLine::split_on("：", "："), pair("", ""));
+        assert_eq!(
+            ParsedLine::split_on("a: b", "→"),
+            ParsedLine::KeylessLine("a: b".to_string())
+        );
+        assert_eq!(ParsedLine::split_on(" \t", "→"), ParsedLine::EmptyLine);
+    }
+}
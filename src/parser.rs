@@ -5,10 +5,17 @@
 //! Process lines incrementally to get key: value pairs using [KVParser]
 
 use core::fmt::Debug;
+use std::{
+    borrow::Cow,
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
 
 use crate::{
+    diagnostics::{lint_whitespace, WhitespaceLint},
     parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
-    KeyValuePair, LineNumber, Output, ParsedLine,
+    InternedKeyValuePair, KeyValuePair, LazyKeyValuePair, LineNumber, MultiLineValue, Output,
+    ParseError, ParsedLine,
 };
 
 #[derive(Debug, Clone)]
@@ -17,17 +24,83 @@ enum State {
     AwaitingCloseText,
 }
 
+/// How [KVParser] trims each line of a multi-line value, in addition to any trimming by the policy.
+///
+/// See [KVParser::with_trim_value_lines].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrimMode {
+    /// Keep lines as returned by the policy.
+    #[default]
+    None,
+    /// Trim whitespace from the end of each line.
+    Trailing,
+    /// Trim whitespace from the start and end of each line.
+    Both,
+}
+
+impl TrimMode {
+    fn apply<'a>(&self, line: &'a str) -> &'a str {
+        match self {
+            TrimMode::None => line,
+            TrimMode::Trailing => line.trim_end(),
+            TrimMode::Both => line.trim(),
+        }
+    }
+}
+
+/// A function transforming complete values, used with [KVParser::with_value_normalizer].
+pub type ValueNormalizer = Box<dyn Fn(&str) -> Cow<'_, str> + Send + Sync>;
+
+/// Wraps a [ValueNormalizer] to allow deriving Debug for [KVParser].
+struct NormalizerFn(ValueNormalizer);
+
+impl Debug for NormalizerFn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("NormalizerFn")
+    }
+}
+
+/// A callback observing the output of every line, used with [KVParser::on_line].
+type LineCallback = Box<dyn FnMut(usize, &Output<KeyValuePair>) + Send>;
+
+/// Wraps a [LineCallback] to allow deriving Debug for [KVParser].
+struct LineCallbackFn(LineCallback);
+
+impl Debug for LineCallbackFn {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("LineCallbackFn")
+    }
+}
+
 /// A parser for key-value pairs (aka tag-value files).
 ///
 /// Parameterized on handling of values to allow different
 /// policies for e.g. handling multi-line values.
 #[derive(Debug)]
-pub struct KVParser<P> {
+pub struct KVParser<P: ParsePolicy> {
     policy: P,
+    policy_state: P::State,
     state: State,
     line_num: usize,
     pending_key: String,
+    pending_line: usize,
     value_lines: Vec<String>,
+    deferred: VecDeque<Output<KeyValuePair>>,
+    key_segment_lens: Vec<usize>,
+    max_key_bytes: Option<usize>,
+    max_value_line_bytes: Option<usize>,
+    interned_keys: Option<HashSet<Arc<str>>>,
+    strict: bool,
+    implicit_multiline_end: bool,
+    trim_value_lines: TrimMode,
+    max_continuation_lines: Option<usize>,
+    continuation_lines: usize,
+    value_normalizer: Option<NormalizerFn>,
+    value_trim_chars: Vec<char>,
+    on_line: Option<LineCallbackFn>,
+    whitespace_lint: bool,
+    lints: Vec<WhitespaceLint>,
+    trim_key_trailing_before_delim: bool,
 }
 
 impl<P: ParsePolicy> KVParser<P> {
@@ -37,20 +110,356 @@ impl<P: ParsePolicy> KVParser<P> {
             state: State::Ready,
             line_num: 0,
             pending_key: String::new(),
+            pending_line: 0,
             value_lines: vec![],
+            deferred: VecDeque::new(),
+            key_segment_lens: vec![],
+            max_key_bytes: None,
+            max_value_line_bytes: None,
+            interned_keys: None,
+            strict: false,
+            implicit_multiline_end: false,
+            trim_value_lines: TrimMode::None,
+            max_continuation_lines: None,
+            continuation_lines: 0,
+            value_normalizer: None,
+            value_trim_chars: vec![],
+            on_line: None,
+            whitespace_lint: false,
+            lints: vec![],
+            trim_key_trailing_before_delim: false,
+            policy_state: P::State::default(),
             policy,
         }
     }
-    fn maybe_push_value_line(&mut self, maybe_value: Option<&str>) {
+
+    /// Reject lines whose key is longer than `max` bytes, returning them as [Output::KeylessLine].
+    ///
+    /// By default, there is no limit.
+    pub fn with_max_key_bytes(mut self, max: usize) -> Self {
+        self.max_key_bytes = Some(max);
+        self
+    }
+
+    /// Reject lines whose value (or multi-line continuation) is longer than `max` bytes,
+    /// returning them as [Output::KeylessLine] rather than accumulating them.
+    /// A rejected continuation line does not end the multi-line value in progress.
+    ///
+    /// By default, there is no limit.
+    pub fn with_max_value_line_bytes(mut self, max: usize) -> Self {
+        self.max_value_line_bytes = Some(max);
+        self
+    }
+
+    /// Share keys between the pairs returned by [KVParser::process_line_interned],
+    /// so that repeated keys are only stored once.
+    pub fn with_key_interning(mut self) -> Self {
+        self.interned_keys = Some(HashSet::new());
+        self
+    }
+
+    /// In strict mode, a line with no key that is not part of a multi-line value
+    /// is returned as [Output::Error] with [ParseError::UnexpectedKeylessLine],
+    /// rather than as [Output::KeylessLine].
+    ///
+    /// This includes lines rejected by [KVParser::with_max_key_bytes] and
+    /// [KVParser::with_max_value_line_bytes], unless they are part of a multi-line value.
+    /// By default, strict mode is off.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Forcibly finish a multi-line value with its `max`th continuation line,
+    /// if the policy has not finished it by then.
+    ///
+    /// This bounds the memory used by unterminated values in untrusted input.
+    /// Lines rejected by [KVParser::with_max_value_line_bytes] are not counted.
+    /// At least one continuation line is always accepted. By default, there is no limit.
+    pub fn with_max_continuation_lines(mut self, max: usize) -> Self {
+        self.max_continuation_lines = Some(max);
+        self
+    }
+
+    /// Transform each complete value, after the policy has processed it,
+    /// for example to canonicalize `Yes`, `yes` and `YES` as `yes`.
+    ///
+    /// Multi-line values are joined before being normalized, so setting a normalizer
+    /// means the values from [KVParser::process_line_lazy] are joined too.
+    /// By default, values are not changed.
+    pub fn with_value_normalizer(mut self, normalizer: ValueNormalizer) -> Self {
+        self.value_normalizer = Some(NormalizerFn(normalizer));
+        self
+    }
+
+    /// Record whitespace style problems in lines with a key, for style-enforcing tools:
+    /// indentation mixing tabs and spaces, and whitespace before the delimiter.
+    ///
+    /// The lines are still parsed as usual, and the problems are collected
+    /// for retrieval with [KVParser::take_lints].
+    /// By default, this is off.
+    pub fn with_whitespace_lint(mut self, enabled: bool) -> Self {
+        self.whitespace_lint = enabled;
+        self
+    }
+
+    /// Take the whitespace style problems found since the last call.
+    ///
+    /// See [KVParser::with_whitespace_lint].
+    pub fn take_lints(&mut self) -> Vec<WhitespaceLint> {
+        core::mem::take(&mut self.lints)
+    }
+
+    /// Trim any of `chars` from both ends of each complete value, after the policy
+    /// has processed it and before any normalizer is applied,
+    /// for example to remove padding like `===value===`.
+    ///
+    /// As with [KVParser::with_value_normalizer], multi-line values are joined first.
+    /// By default, no characters are trimmed.
+    pub fn with_value_trim_chars(mut self, chars: &[char]) -> Self {
+        self.value_trim_chars = chars.to_vec();
+        self
+    }
+
+    /// Whether complete values are changed, by trimming or a normalizer.
+    fn transforms_values(&self) -> bool {
+        self.value_normalizer.is_some() || !self.value_trim_chars.is_empty()
+    }
+
+    fn normalize_value(&self, value: Cow<str>) -> String {
+        let value = value.trim_matches(self.value_trim_chars.as_slice());
+        match &self.value_normalizer {
+            Some(NormalizerFn(normalizer)) => normalizer(value).into_owned(),
+            None => value.to_string(),
+        }
+    }
+
+    /// Trim whitespace from the end of keys, so `key : value` has the key `key` rather than `key `,
+    /// for formats where whitespace before the delimiter belongs to neither the key nor the value.
+    ///
+    /// Unlike [ParsePolicy::trim_keys], leading whitespace such as indentation is kept.
+    /// By default, this is off.
+    pub fn with_trim_key_trailing_before_delim(mut self, trim: bool) -> Self {
+        self.trim_key_trailing_before_delim = trim;
+        self
+    }
+
+    /// While a multi-line value is in progress, treat a line that the policy would continue the value with,
+    /// but that itself splits into a key and value with [ParsePolicy::parse_line],
+    /// as the start of a new pair, as with [ProcessedContinuationValue::AbortAndReprocess].
+    ///
+    /// This recovers from multi-line values missing their terminator, at the cost of ending
+    /// values that legitimately contain such lines.
+    /// By default, this is off.
+    pub fn with_implicit_multiline_end(mut self, implicit_multiline_end: bool) -> Self {
+        self.implicit_multiline_end = implicit_multiline_end;
+        self
+    }
+
+    /// Trim each line added to a multi-line value, after the policy has processed it.
+    ///
+    /// Lines appended to a previous line with [ProcessedContinuationValue::AppendToPrevious]
+    /// are not trimmed. By default, lines are not trimmed.
+    pub fn with_trim_value_lines(mut self, mode: TrimMode) -> Self {
+        self.trim_value_lines = mode;
+        self
+    }
+
+    fn intern_key(&mut self, key: &str) -> Arc<str> {
+        match &mut self.interned_keys {
+            Some(interned_keys) => match interned_keys.get(key) {
+                Some(interned) => Arc::clone(interned),
+                None => {
+                    let interned: Arc<str> = key.into();
+                    interned_keys.insert(Arc::clone(&interned));
+                    interned
+                }
+            },
+            None => key.into(),
+        }
+    }
+
+    /// Process a continuation line with the policy, applying [KVParser::with_implicit_multiline_end]
+    /// and [KVParser::with_max_continuation_lines].
+    ///
+    /// The policy state is passed in, so that [KVParser::would_finish] can pass a copy.
+    fn process_continuation<'a>(
+        &self,
+        state: &mut P::State,
+        line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        let at_max_lines = matches!(
+            self.max_continuation_lines,
+            Some(max) if self.continuation_lines + 1 >= max
+        );
+        match self
+            .policy
+            .process_continuation(state, &self.pending_key, line)
+        {
+            ProcessedContinuationValue::ContinueMultiline(_)
+            | ProcessedContinuationValue::AppendToPrevious(_)
+                if self.implicit_multiline_end
+                    && matches!(
+                        self.policy.parse_line(&mut state.clone(), line),
+                        ParsedLine::Pair(_)
+                    ) =>
+            {
+                ProcessedContinuationValue::AbortAndReprocess
+            }
+            ProcessedContinuationValue::ContinueMultiline(value) if at_max_lines => {
+                ProcessedContinuationValue::FinishMultiline(value)
+            }
+            ProcessedContinuationValue::AppendToPrevious(value) if at_max_lines => {
+                ProcessedContinuationValue::FinishAppendToPrevious(value)
+            }
+            continuation => continuation,
+        }
+    }
+
+    fn exceeds(limit: Option<usize>, s: &str) -> bool {
+        matches!(limit, Some(max) if s.len() > max)
+    }
+    fn maybe_push_value_line(&mut self, maybe_value: Option<Cow<str>>) {
         if let Some(value) = maybe_value {
-            self.value_lines.push(value.to_string())
+            let trimmed = self.trim_value_lines.apply(&value);
+            if trimmed.len() == value.len() {
+                self.value_lines.push(value.into_owned())
+            } else {
+                self.value_lines.push(trimmed.to_string())
+            }
+        }
+    }
+    fn append_to_previous_value_line(&mut self, value: Cow<str>) {
+        match self.value_lines.last_mut() {
+            Some(last) => last.push_str(&value),
+            None => self.value_lines.push(value.into_owned()),
         }
     }
-    fn take_pending(&mut self) -> KeyValuePair {
-        let value = self.value_lines.join("\n");
-        self.value_lines.clear();
+    fn take_pending(&mut self) -> LazyKeyValuePair {
+        let mut value = MultiLineValue::new(core::mem::take(&mut self.value_lines));
+        if self.transforms_values() {
+            value = MultiLineValue::new(vec![self.normalize_value(value.into_string().into())]);
+        }
         let key = core::mem::take(&mut self.pending_key);
-        KeyValuePair { key, value }
+        LazyKeyValuePair { key, value }
+    }
+
+    /// Process a line that is not part of a multi-line value.
+    fn process_ready_line(&mut self, line: &str) -> Output<KeyValuePair> {
+        match self.process_ready_line_lenient(line) {
+            Output::KeylessLine(v) if self.strict => {
+                Output::Error(ParseError::UnexpectedKeylessLine(v))
+            }
+            output => output,
+        }
+    }
+
+    fn process_ready_line_lenient(&mut self, line: &str) -> Output<KeyValuePair> {
+        if self.policy.is_comment(line) {
+            return Output::Comment(line.to_string());
+        }
+        let parsed = self.policy.parse_line(&mut self.policy_state, line);
+        if let (true, ParsedLine::Pair(pair)) = (self.whitespace_lint, &parsed) {
+            let line_num = self.line_num;
+            self.lints.extend(
+                lint_whitespace(line, &pair.key).map(|issue| WhitespaceLint {
+                    line: line_num,
+                    issue,
+                }),
+            );
+        }
+        let parsed = match parsed {
+            ParsedLine::Pair(mut pair) if self.policy.trim_keys() => {
+                let trimmed = pair.key.trim();
+                if trimmed.len() != pair.key.len() {
+                    pair.key = trimmed.to_string();
+                }
+                ParsedLine::Pair(pair)
+            }
+            ParsedLine::Pair(mut pair) if self.trim_key_trailing_before_delim => {
+                pair.key.truncate(pair.key.trim_end().len());
+                ParsedLine::Pair(pair)
+            }
+            parsed => parsed,
+        };
+        match parsed {
+            ParsedLine::EmptyLine => Output::EmptyLine,
+            ParsedLine::KeylessLine(v) => Output::KeylessLine(v),
+            ParsedLine::Pair(ref pair)
+                if Self::exceeds(self.max_key_bytes, &pair.key)
+                    || Self::exceeds(self.max_value_line_bytes, &pair.value) =>
+            {
+                Output::KeylessLine(line.to_string())
+            }
+            ParsedLine::Pair(pair) => {
+                self.key_segment_lens.push(line.trim_end().len());
+                match self
+                    .policy
+                    .process_value(&mut self.policy_state, &pair.key, &pair.value)
+                {
+                    ProcessedValue::CompleteValue(value) => Output::Output(KeyValuePair {
+                        value: self.normalize_value(value),
+                        key: pair.key,
+                    }),
+                    ProcessedValue::StartOfMultiline(maybe_value) => {
+                        self.pending_key = pair.key;
+                        self.pending_line = self.line_num;
+                        self.value_lines.clear();
+                        self.continuation_lines = 0;
+                        self.maybe_push_value_line(maybe_value);
+                        Output::Pending
+                    }
+                    ProcessedValue::CompleteValueAndReprocessRest(value, rest) => {
+                        let complete = Output::Output(KeyValuePair {
+                            value: self.normalize_value(value),
+                            key: pair.key,
+                        });
+                        self.complete_and_reprocess(complete, rest)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return a complete pair, after processing `rest` as though it were a line of its own,
+    /// and hold the output of `rest` for [KVParser::take_deferred_output].
+    ///
+    /// If `rest` started a new multi-line value, return [Output::Pending] instead,
+    /// and hold the complete pair.
+    fn complete_and_reprocess(
+        &mut self,
+        complete: Output<KeyValuePair>,
+        rest: &str,
+    ) -> Output<KeyValuePair> {
+        match self.process_ready_line(rest) {
+            Output::Pending => {
+                self.deferred.push_front(complete);
+                Output::Pending
+            }
+            reprocessed => {
+                self.deferred.push_front(reprocessed);
+                complete
+            }
+        }
+    }
+
+    /// Get the policy this parser was created with.
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// Get the policy this parser was created with, mutably.
+    ///
+    /// Changing the policy while a multi-line value is in progress affects how the rest
+    /// of that value is processed, so it is best done between values,
+    /// when [KVParser::pending_start_line] returns `None`.
+    pub fn policy_mut(&mut self) -> &mut P {
+        &mut self.policy
+    }
+
+    /// Get the state of the policy, as carried from one line to the next.
+    pub fn policy_state(&self) -> &P::State {
+        &self.policy_state
     }
 
     /// The number of lines that we have processed.
@@ -58,35 +467,101 @@ impl<P: ParsePolicy> KVParser<P> {
         self.line_num
     }
 
+    /// The line number where the multi-line value in progress started, if any.
+    pub fn pending_start_line(&self) -> Option<usize> {
+        match &self.state {
+            State::Ready => None,
+            State::AwaitingCloseText => Some(self.pending_line),
+        }
+    }
+
     /// Pass a line to process and advance the state of the parser.
     ///
     /// If a complete key: value pair is now available, it will
     /// be found in the return value.
+    ///
+    /// If the policy aborted a multi-line value with
+    /// [ProcessedContinuationValue::AbortAndReprocess], the aborted pair is returned,
+    /// and the output from re-processing this line (if it is not [Output::Pending])
+    /// is held for retrieval with [KVParser::take_deferred_output].
+    ///
+    /// Anything needed from the line is copied, so the caller may reuse its buffer
+    /// for the next line, as [KVParser::parse_reader] does.
     pub fn process_line(&mut self, line: &str) -> LineNumber<Output<KeyValuePair>> {
+        let output = self.process_line_unobserved(line);
+        self.notify(&output);
+        output
+    }
+
+    /// Set a callback to call with the line number and output of every line
+    /// passed to [KVParser::process_line] or [KVParser::process_line_lazy], whatever its variant,
+    /// for example to count the kinds of lines in a document.
+    ///
+    /// The callback is also called with any output held for [KVParser::take_deferred_output],
+    /// when it is produced, and with the output of [KVParser::finish].
+    /// This replaces any previous callback.
+    pub fn on_line(&mut self, callback: impl FnMut(usize, &Output<KeyValuePair>) + Send + 'static) {
+        self.on_line = Some(LineCallbackFn(Box::new(callback)));
+    }
+
+    /// Call the callback set with [KVParser::on_line], if any, with an output and any deferred output.
+    fn notify(&mut self, output: &LineNumber<Output<KeyValuePair>>) {
+        if let Some(LineCallbackFn(callback)) = &mut self.on_line {
+            callback(output.line_number(), output.value());
+            for deferred in &self.deferred {
+                callback(self.line_num, deferred);
+            }
+        }
+    }
+
+    fn process_line_unobserved(&mut self, line: &str) -> LineNumber<Output<KeyValuePair>> {
+        if self.policy.never_multiline() && matches!(self.state, State::Ready) {
+            // Fast path: skip the multi-line value machinery entirely.
+            self.line_num += 1;
+            self.deferred.clear();
+            self.key_segment_lens.clear();
+            let output = self.process_ready_line(line);
+            if output.is_pending() {
+                self.state = State::AwaitingCloseText;
+            }
+            return LineNumber::new(self.line_num, output);
+        }
+        self.process_line_lazy_unobserved(line)
+            .map(|output| output.map(Into::into))
+    }
+
+    /// Like [KVParser::process_line], but the lines of a multi-line value are not joined
+    /// until requested, avoiding the allocation if the value is not used.
+    ///
+    /// If a callback is set with [KVParser::on_line], the value is joined to pass to it.
+    pub fn process_line_lazy(&mut self, line: &str) -> LineNumber<Output<LazyKeyValuePair>> {
+        let output = self.process_line_lazy_unobserved(line);
+        if self.on_line.is_some() {
+            self.notify(&output.clone().map(|output| output.map(Into::into)));
+        }
+        output
+    }
+
+    fn process_line_lazy_unobserved(&mut self, line: &str) -> LineNumber<Output<LazyKeyValuePair>> {
         self.line_num += 1;
+        self.deferred.clear();
+        self.key_segment_lens.clear();
 
         // Match on our current state to compute our output.
         //
         // The output also uniquely determines our next state.
         let output = match &mut self.state {
-            State::Ready => match ParsedLine::from(line) {
-                ParsedLine::EmptyLine => Output::EmptyLine,
-                ParsedLine::KeylessLine(v) => Output::KeylessLine(v),
-                ParsedLine::Pair(pair) => match self.policy.process_value(&pair.key, &pair.value) {
-                    ProcessedValue::CompleteValue(value) => Output::Output(KeyValuePair {
-                        key: pair.key,
-                        value: value.to_string(),
-                    }),
-                    ProcessedValue::StartOfMultiline(maybe_value) => {
-                        self.pending_key = pair.key;
-                        self.value_lines.clear();
-                        self.maybe_push_value_line(maybe_value);
-                        Output::Pending
-                    }
-                },
-            },
+            State::Ready => self.process_ready_line(line).map(Into::into),
+            State::AwaitingCloseText if Self::exceeds(self.max_value_line_bytes, line) => {
+                // Reject the line, but remain in the multi-line value.
+                return LineNumber::new(self.line_num, Output::KeylessLine(line.to_string()));
+            }
             State::AwaitingCloseText => {
-                match self.policy.process_continuation(&self.pending_key, line) {
+                let mut state = core::mem::take(&mut self.policy_state);
+                let continuation = self.process_continuation(&mut state, line);
+                self.policy_state = state;
+                self.continuation_lines += 1;
+                match continuation {
                     ProcessedContinuationValue::ContinueMultiline(maybe_value) => {
                         self.maybe_push_value_line(maybe_value);
                         Output::Pending
@@ -95,6 +570,23 @@ impl<P: ParsePolicy> KVParser<P> {
                         self.maybe_push_value_line(maybe_value);
                         Output::Output(self.take_pending())
                     }
+                    ProcessedContinuationValue::AppendToPrevious(value) => {
+                        self.append_to_previous_value_line(value);
+                        Output::Pending
+                    }
+                    ProcessedContinuationValue::FinishAppendToPrevious(value) => {
+                        self.append_to_previous_value_line(value);
+                        Output::Output(self.take_pending())
+                    }
+                    ProcessedContinuationValue::AbortAndReprocess => {
+                        let aborted = self.take_pending();
+                        return self.finish_and_reprocess(aborted, line);
+                    }
+                    ProcessedContinuationValue::FinishAndReprocessRest(maybe_value, rest) => {
+                        self.maybe_push_value_line(maybe_value);
+                        let finished = self.take_pending();
+                        return self.finish_and_reprocess(finished, rest);
+                    }
                 }
             }
         };
@@ -106,9 +598,160 @@ impl<P: ParsePolicy> KVParser<P> {
         LineNumber::new(self.line_num, output)
     }
 
+    /// Return a finished pair, after processing `line` as though no multi-line value were in progress.
+    ///
+    /// The output of `line`, along with any outputs it defers, is held for
+    /// [KVParser::take_deferred_output], unless it started a new multi-line value.
+    fn finish_and_reprocess(
+        &mut self,
+        finished: LazyKeyValuePair,
+        line: &str,
+    ) -> LineNumber<Output<LazyKeyValuePair>> {
+        match self.process_ready_line(line) {
+            Output::Pending => self.state = State::AwaitingCloseText,
+            reprocessed => {
+                self.deferred.push_front(reprocessed);
+                self.state = State::Ready;
+            }
+        }
+        LineNumber::new(self.line_num, Output::Output(finished))
+    }
+
+    /// Like [KVParser::process_line], but returns pairs whose keys are shared with
+    /// earlier pairs with the same key, if enabled with [KVParser::with_key_interning].
+    ///
+    /// Without interning enabled, each key is stored separately.
+    pub fn process_line_interned(
+        &mut self,
+        line: &str,
+    ) -> LineNumber<Output<InternedKeyValuePair>> {
+        self.process_line(line).map(|output| {
+            output.map(|pair| InternedKeyValuePair {
+                key: self.intern_key(&pair.key),
+                value: pair.value,
+            })
+        })
+    }
+
+    /// Returns true if passing this line to [KVParser::process_line] would finish
+    /// the multi-line value in progress (including by aborting it), without changing any state.
+    ///
+    /// Always false if no multi-line value is in progress.
+    pub fn would_finish(&self, line: &str) -> bool {
+        match &self.state {
+            State::Ready => false,
+            State::AwaitingCloseText if Self::exceeds(self.max_value_line_bytes, line) => false,
+            State::AwaitingCloseText => !matches!(
+                self.process_continuation(&mut self.policy_state.clone(), line),
+                ProcessedContinuationValue::ContinueMultiline(_)
+                    | ProcessedContinuationValue::AppendToPrevious(_)
+            ),
+        }
+    }
+
+    /// Take the next output held from the most recent line, in order, if any.
+    ///
+    /// The most recent line has more than one output if the policy
+    /// aborted a multi-line value with [ProcessedContinuationValue::AbortAndReprocess]
+    /// and that line produced output of its own, or if the line is packed with more than one pair,
+    /// as with [ProcessedContinuationValue::FinishAndReprocessRest]
+    /// and [ProcessedValue::CompleteValueAndReprocessRest].
+    ///
+    /// Call this until it returns `None` after each call to [KVParser::process_line]:
+    /// any outputs left are discarded by the next call.
+    pub fn take_deferred_output(&mut self) -> Option<LineNumber<Output<KeyValuePair>>> {
+        self.deferred
+            .pop_front()
+            .map(|output| LineNumber::new(self.line_num, output))
+    }
+
+    /// Take the length, without trailing whitespace, of the rest of the most recent line
+    /// from the start of each key parsed on it, in order.
+    fn take_key_segment_lens(&mut self) -> Vec<usize> {
+        core::mem::take(&mut self.key_segment_lens)
+    }
+
     /// Take the pending key: value pair, if any, and treat it as having completed.
     /// For example, this may be useful at the end of input.
     pub fn take_pending_pair(&mut self) -> Option<KeyValuePair> {
+        self.take_pending_pair_lazy().map(Into::into)
+    }
+
+    /// Signal the end of input, ending any multi-line value in progress.
+    ///
+    /// The policy decides how a pending pair ends, with [ParsePolicy::finish].
+    /// If it finishes the value, the pair is returned as [Output::Output].
+    /// Otherwise, unlike [KVParser::take_pending_pair], which treats the pending pair as complete,
+    /// a pending pair is returned as [Output::Error] with [ParseError::UnterminatedMultiline].
+    /// Either way, the output is numbered with the line where the value started.
+    /// For example, `key: <text>abc` followed by the end of input gives the key `key` and value `abc`.
+    /// Returns `None` if no multi-line value is in progress.
+    ///
+    /// If the policy returns [ProcessedContinuationValue::FinishAndReprocessRest],
+    /// the output of the rest is held for [KVParser::take_deferred_output],
+    /// and if it starts a new multi-line value, call this again to end that one too.
+    pub fn finish(&mut self) -> Option<LineNumber<Output<KeyValuePair>>> {
+        let line = self.pending_start_line()?;
+        self.deferred.clear();
+        self.key_segment_lens.clear();
+        let output = match self.policy.finish(&mut self.policy_state) {
+            Some(ProcessedContinuationValue::FinishMultiline(maybe_value)) => {
+                self.maybe_push_value_line(maybe_value);
+                Output::Output(self.take_pending_pair()?)
+            }
+            Some(ProcessedContinuationValue::FinishAppendToPrevious(value)) => {
+                self.append_to_previous_value_line(value);
+                Output::Output(self.take_pending_pair()?)
+            }
+            Some(ProcessedContinuationValue::AbortAndReprocess) => {
+                Output::Output(self.take_pending_pair()?)
+            }
+            Some(ProcessedContinuationValue::FinishAndReprocessRest(maybe_value, rest)) => {
+                self.maybe_push_value_line(maybe_value);
+                let finished = self.take_pending();
+                self.finish_and_reprocess(finished, rest)
+                    .into_inner()
+                    .map(Into::into)
+            }
+            Some(ProcessedContinuationValue::ContinueMultiline(maybe_value)) => {
+                self.maybe_push_value_line(maybe_value);
+                self.unterminated_pending_pair()?
+            }
+            Some(ProcessedContinuationValue::AppendToPrevious(value)) => {
+                self.append_to_previous_value_line(value);
+                self.unterminated_pending_pair()?
+            }
+            None => self.unterminated_pending_pair()?,
+        };
+        let output = LineNumber::new(line, output);
+        self.notify(&output);
+        Some(output)
+    }
+
+    /// Call [KVParser::finish] until no multi-line value is in progress,
+    /// passing each output, and any deferred output, to `on_output`.
+    pub(crate) fn finish_each(
+        &mut self,
+        mut on_output: impl FnMut(LineNumber<Output<KeyValuePair>>),
+    ) {
+        while let Some(output) = self.finish() {
+            on_output(output);
+            while let Some(output) = self.take_deferred_output() {
+                on_output(output);
+            }
+        }
+    }
+
+    fn unterminated_pending_pair(&mut self) -> Option<Output<KeyValuePair>> {
+        let KeyValuePair { key, value } = self.take_pending_pair()?;
+        Some(Output::Error(ParseError::UnterminatedMultiline {
+            key,
+            value,
+        }))
+    }
+
+    /// Like [KVParser::take_pending_pair], but the lines of the value are not joined.
+    pub fn take_pending_pair_lazy(&mut self) -> Option<LazyKeyValuePair> {
         match &self.state {
             State::Ready => None,
             State::AwaitingCloseText => {
@@ -117,63 +760,418 @@ impl<P: ParsePolicy> KVParser<P> {
             }
         }
     }
-}
 
-impl<P: ParsePolicy + Debug + Default> Default for KVParser<P> {
-    fn default() -> Self {
-        Self::new(P::default())
+    /// Iterate over the output of processing each line of a string.
+    ///
+    /// Lines are split as by [str::lines].
+    /// At the end of input, any multi-line value in progress is ended with [KVParser::finish].
+    pub fn lines<'p, 'i>(&'p mut self, input: &'i str) -> Lines<'p, 'i, P> {
+        Lines {
+            parser: self,
+            remaining: Some(input),
+            current_line: "",
+        }
     }
-}
-
-#[cfg(test)]
-mod test {
-
-    use crate::parse_policy::ParsePolicy;
-    use crate::policies::SPDXParsePolicy;
-    use crate::policies::TrivialParsePolicy;
-    use crate::ParserOutput;
 
-    use super::KVParser;
-    use super::KeyValuePair;
-    use super::LineNumber;
-    use super::Output;
+    /// Like [KVParser::lines], but each output is paired with the line that produced it,
+    /// without its line ending.
+    ///
+    /// The output of [KVParser::finish] at the end of input is paired with an empty line.
+    pub fn lines_with_raw<'p, 'i>(&'p mut self, input: &'i str) -> RawLines<'p, 'i, P> {
+        RawLines {
+            inner: self.lines(input),
+        }
+    }
 
-    #[test]
-    fn basics() {
-        fn test_parser<P: ParsePolicy>(mut parser: KVParser<P>) {
-            assert_eq!(
-                parser.process_line("key1: value1"),
-                LineNumber::new(
-                    1,
-                    Output::Output(KeyValuePair {
-                        key: "key1".to_string(),
-                        value: "value1".to_string(),
-                    })
-                )
-            );
-            assert_eq!(
-                parser.process_line(" "),
-                LineNumber::new(2, Output::EmptyLine)
-            );
-            assert_eq!(
-                parser.process_line("key2: value2"),
-                LineNumber::new(
-                    3,
-                    Output::Output(KeyValuePair {
-                        key: "key2".to_string(),
-                        value: "value2".to_string(),
-                    })
-                )
-            );
+    /// Like [KVParser::lines_with_raw], but each completed pair also has the location and
+    /// exact text of the delimiter on the line where the pair started: see [DelimiterInfo].
+    ///
+    /// `delimiter` should be the delimiter the policy splits lines on, such as `": "`.
+    pub fn lines_with_delimiters<'p, 'i, 'd>(
+        &'p mut self,
+        input: &'i str,
+        delimiter: &'d str,
+    ) -> DelimitedLines<'p, 'i, 'd, P> {
+        // A value already in progress started on a line we do not have.
+        let delimiters = self
+            .pending_start_line()
+            .map(|_| None)
+            .into_iter()
+            .collect();
+        DelimitedLines {
+            inner: self.lines_with_raw(input),
+            delimiter,
+            delimiters,
         }
-        let parser: KVParser<TrivialParsePolicy> = KVParser::default();
-        test_parser(parser);
+    }
 
-        let parser: KVParser<SPDXParsePolicy> = KVParser::default();
-        test_parser(parser);
+    /// Iterate over only the keyless lines of a string, with their line numbers,
+    /// for example to extract comments or headers.
+    ///
+    /// Lines are processed as by [KVParser::lines], so lines that are part of
+    /// a multi-line value are not keyless, and all other outputs are skipped.
+    pub fn parse_str_keyless<'a>(
+        &'a mut self,
+        input: &'a str,
+    ) -> impl Iterator<Item = LineNumber<String>> + 'a {
+        self.lines(input).filter_map(|output| {
+            let (line_number, output) = output.into_tuple();
+            match output {
+                Output::KeylessLine(line) => Some(LineNumber::new(line_number, line)),
+                _ => None,
+            }
+        })
     }
 
-    #[test]
+    /// Pass lines from a fallible source, such as [std::io::BufRead::lines], to process.
+    ///
+    /// The returned iterator yields the output for each line, and at the end of input,
+    /// the output of [KVParser::finish].
+    /// It stops after yielding the first error from the source.
+    pub fn parse_results<E, I: IntoIterator<Item = Result<String, E>>>(
+        &mut self,
+        lines: I,
+    ) -> ParseResults<'_, P, I::IntoIter> {
+        ParseResults {
+            parser: self,
+            lines: Some(lines.into_iter()),
+        }
+    }
+
+    /// Process every line of a reader, passing each output to a callback,
+    /// then the output of [KVParser::finish] at the end of input.
+    ///
+    /// A single buffer is reused for every line, rather than allocating a `String` per line
+    /// as [std::io::BufRead::lines] does.
+    /// Line endings (`\n` or `\r\n`) are removed, as by [str::lines].
+    #[cfg(feature = "std")]
+    pub fn parse_reader<R: std::io::BufRead>(
+        &mut self,
+        mut reader: R,
+        mut on_output: impl FnMut(LineNumber<Output<KeyValuePair>>),
+    ) -> std::io::Result<()> {
+        let mut buf = String::new();
+        while reader.read_line(&mut buf)? != 0 {
+            let line = buf.strip_suffix('\n').unwrap_or(&buf);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            on_output(self.process_line(line));
+            while let Some(output) = self.take_deferred_output() {
+                on_output(output);
+            }
+            buf.clear();
+        }
+        self.finish_each(on_output);
+        Ok(())
+    }
+}
+
+/// Iterator returned by [KVParser::lines].
+#[derive(Debug)]
+pub struct Lines<'p, 'i, P: ParsePolicy> {
+    parser: &'p mut KVParser<P>,
+    remaining: Option<&'i str>,
+    current_line: &'i str,
+}
+
+impl<'p, 'i, P: ParsePolicy> Lines<'p, 'i, P> {
+    fn remaining_lines(&self) -> usize {
+        match self.remaining {
+            Some(remaining) if !remaining.is_empty() => {
+                remaining.matches('\n').count() + usize::from(!remaining.ends_with('\n'))
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl<'p, 'i, P: ParsePolicy> Iterator for Lines<'p, 'i, P> {
+    type Item = LineNumber<Output<KeyValuePair>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(output) = self.parser.take_deferred_output() {
+            return Some(output);
+        }
+        let remaining = self.remaining?;
+        if remaining.is_empty() {
+            self.current_line = "";
+            let output = self.parser.finish();
+            if output.is_none() {
+                self.remaining = None;
+            }
+            return output;
+        }
+        let (line, rest) = match remaining.find('\n') {
+            Some(end) => (&remaining[..end], &remaining[end + 1..]),
+            None => (remaining, ""),
+        };
+        self.remaining = Some(rest);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        self.current_line = line;
+        Some(self.parser.process_line(line))
+    }
+
+    /// Each remaining line produces at least one output. There is no upper bound,
+    /// since a line may produce any number of deferred outputs, such as packed pairs.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining_lines(), None)
+    }
+}
+
+/// Iterator returned by [KVParser::lines_with_raw].
+#[derive(Debug)]
+pub struct RawLines<'p, 'i, P: ParsePolicy> {
+    inner: Lines<'p, 'i, P>,
+}
+
+impl<'p, 'i, P: ParsePolicy> Iterator for RawLines<'p, 'i, P> {
+    type Item = (&'i str, LineNumber<Output<KeyValuePair>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let output = self.inner.next()?;
+        Some((self.inner.current_line, output))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Where the delimiter between a key and value was found on a line, including the whitespace
+/// surrounding it, to reproduce the original spacing such as `key   :   value` when rewriting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelimiterInfo {
+    /// The byte offset of the start of `matched` within the line.
+    pub byte_offset: usize,
+    /// The delimiter as it appears in the line, with any surrounding whitespace.
+    pub matched: String,
+}
+
+impl DelimiterInfo {
+    /// Find the first occurrence of `delimiter` in a line, as the policy splits on it,
+    /// then widen it over any whitespace around it in the line.
+    pub fn locate(line: &str, delimiter: &str) -> Option<Self> {
+        let found = line.find(delimiter)?;
+        let start = line[..found].trim_end().len();
+        let end = line.len() - line[found + delimiter.len()..].trim_start().len();
+        Some(Self {
+            byte_offset: start,
+            matched: line[start..end].to_string(),
+        })
+    }
+}
+
+/// Iterator returned by [KVParser::lines_with_delimiters].
+#[derive(Debug)]
+pub struct DelimitedLines<'p, 'i, 'd, P: ParsePolicy> {
+    inner: RawLines<'p, 'i, P>,
+    delimiter: &'d str,
+    /// The delimiters of the pairs not yet output, in the order their keys were parsed.
+    delimiters: VecDeque<Option<DelimiterInfo>>,
+}
+
+impl<'p, 'i, 'd, P: ParsePolicy> Iterator for DelimitedLines<'p, 'i, 'd, P> {
+    type Item = (
+        &'i str,
+        Option<DelimiterInfo>,
+        LineNumber<Output<KeyValuePair>>,
+    );
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (raw, output) = self.inner.next()?;
+        // Pairs are output in the order their keys were parsed,
+        // each key starting a segment that runs to the end of the line.
+        let segments = self.inner.inner.parser.take_key_segment_lens();
+        let (trimmed, delimiter) = (raw.trim_end(), self.delimiter);
+        self.delimiters.extend(segments.into_iter().map(|len| {
+            let offset = trimmed.len().checked_sub(len)?;
+            let found = DelimiterInfo::locate(raw.get(offset..)?, delimiter)?;
+            Some(DelimiterInfo {
+                byte_offset: offset + found.byte_offset,
+                ..found
+            })
+        }));
+        let delimiter = match output.value() {
+            Output::Output(_) => self.delimiters.pop_front().flatten(),
+            Output::Error(ParseError::UnterminatedMultiline { .. }) => {
+                self.delimiters.pop_front();
+                None
+            }
+            _ => None,
+        };
+        Some((raw, delimiter, output))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Iterator returned by [KVParser::parse_results].
+#[derive(Debug)]
+pub struct ParseResults<'a, P: ParsePolicy, I> {
+    parser: &'a mut KVParser<P>,
+    lines: Option<I>,
+}
+
+impl<'a, P: ParsePolicy, E, S: AsRef<str>, I: Iterator<Item = Result<S, E>>> Iterator
+    for ParseResults<'a, P, I>
+{
+    type Item = Result<LineNumber<Output<KeyValuePair>>, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(output) = self.parser.take_deferred_output() {
+            return Some(Ok(output));
+        }
+        match self.lines.as_mut()?.next() {
+            Some(Ok(line)) => Some(Ok(self.parser.process_line(line.as_ref()))),
+            Some(Err(e)) => {
+                self.lines = None;
+                Some(Err(e))
+            }
+            None => {
+                let output = self.parser.finish();
+                if output.is_none() {
+                    self.lines = None;
+                }
+                output.map(Ok)
+            }
+        }
+    }
+}
+
+/// An iterator owning a reader and a [KVParser], parsing one line of the reader per call to `next`.
+///
+/// A single buffer is reused for every line, as with [KVParser::parse_reader].
+/// At the end of input, any multi-line value in progress is ended with [KVParser::finish].
+/// It stops after yielding the first error from the reader.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct LineReaderParser<R, P: ParsePolicy> {
+    reader: R,
+    parser: KVParser<P>,
+    buf: String,
+    done: bool,
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::BufRead, P: ParsePolicy> LineReaderParser<R, P> {
+    /// Create an iterator parsing the lines of `reader` with `parser`.
+    pub fn new(reader: R, parser: KVParser<P>) -> Self {
+        Self {
+            reader,
+            parser,
+            buf: String::new(),
+            done: false,
+        }
+    }
+
+    /// Get a shared borrow of the parser, for example to check [KVParser::lines_processed].
+    pub fn parser(&self) -> &KVParser<P> {
+        &self.parser
+    }
+
+    /// Unwrap the reader and parser.
+    pub fn into_inner(self) -> (R, KVParser<P>) {
+        (self.reader, self.parser)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::BufRead, P: ParsePolicy> Iterator for LineReaderParser<R, P> {
+    type Item = std::io::Result<LineNumber<Output<KeyValuePair>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(output) = self.parser.take_deferred_output() {
+            return Some(Ok(output));
+        }
+        if self.done {
+            return None;
+        }
+        self.buf.clear();
+        match self.reader.read_line(&mut self.buf) {
+            Ok(0) => {
+                let output = self.parser.finish();
+                self.done = output.is_none();
+                output.map(Ok)
+            }
+            Ok(_) => {
+                let line = self.buf.strip_suffix('\n').unwrap_or(&self.buf);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                Some(Ok(self.parser.process_line(line)))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<P: ParsePolicy + Debug + Default> Default for KVParser<P> {
+    fn default() -> Self {
+        Self::new(P::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::diagnostics::{WhitespaceIssue, WhitespaceLint};
+    use crate::parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue};
+    use crate::policies::SPDXParsePolicy;
+    use crate::policies::TrimKeysPolicy;
+    use crate::policies::TrivialParsePolicy;
+    use crate::policies::{IndentScopePolicy, WhitespaceNormalizePolicy};
+    use crate::ParserOutput;
+
+    use std::sync::{Arc, Mutex};
+
+    use super::DelimiterInfo;
+    use super::KVParser;
+    use super::KeyValuePair;
+    use super::LineNumber;
+    #[cfg(feature = "std")]
+    use super::LineReaderParser;
+    use super::Output;
+    use super::ParseError;
+    use super::TrimMode;
+
+    #[test]
+    fn basics() {
+        fn test_parser<P: ParsePolicy>(mut parser: KVParser<P>) {
+            assert_eq!(
+                parser.process_line("key1: value1"),
+                LineNumber::new(
+                    1,
+                    Output::Output(KeyValuePair {
+                        key: "key1".to_string(),
+                        value: "value1".to_string(),
+                    })
+                )
+            );
+            assert_eq!(
+                parser.process_line(" "),
+                LineNumber::new(2, Output::EmptyLine)
+            );
+            assert_eq!(
+                parser.process_line("key2: value2"),
+                LineNumber::new(
+                    3,
+                    Output::Output(KeyValuePair {
+                        key: "key2".to_string(),
+                        value: "value2".to_string(),
+                    })
+                )
+            );
+        }
+        let parser: KVParser<TrivialParsePolicy> = KVParser::default();
+        test_parser(parser);
+
+        let parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        test_parser(parser);
+    }
+
+    #[test]
     fn trim_same_line() {
         let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
         assert_eq!(
@@ -203,4 +1201,738 @@ value"
         );
         assert_eq!(parser.process_line("").into_inner(), Output::EmptyLine);
     }
+
+    #[test]
+    fn would_finish() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        assert!(!parser.would_finish("value</text>"));
+        assert!(parser.process_line("key: <text>value").ok().is_none());
+
+        assert!(!parser.would_finish(""));
+        assert!(parser.would_finish("value</text>"));
+        assert_eq!(parser.process_line("").into_inner(), Output::Pending);
+        assert!(parser.would_finish("value</text>"));
+        assert!(parser.process_line("value</text>").ok().is_some());
+        assert!(!parser.would_finish("value</text>"));
+    }
+
+    /// Like SPDX, but a continuation line that looks like a new key aborts the value.
+    #[derive(Debug, Default)]
+    struct AbortOnKeyPolicy {}
+    impl ParsePolicy for AbortOnKeyPolicy {
+        type State = ();
+
+        fn process_value<'a>(
+            &self,
+            _state: &mut (),
+            key: &str,
+            value: &'a str,
+        ) -> ProcessedValue<'a> {
+            SPDXParsePolicy {}.process_value(&mut (), key, value)
+        }
+
+        fn process_continuation<'a>(
+            &self,
+            _state: &mut (),
+            key: &str,
+            continuation_line: &'a str,
+        ) -> ProcessedContinuationValue<'a> {
+            if continuation_line.contains(": ") {
+                ProcessedContinuationValue::AbortAndReprocess
+            } else {
+                SPDXParsePolicy {}.process_continuation(&mut (), key, continuation_line)
+            }
+        }
+    }
+
+    #[test]
+    fn abort_and_reprocess() {
+        let mut parser: KVParser<AbortOnKeyPolicy> = KVParser::default();
+        assert!(parser.process_line("key: <text>value").value().is_pending());
+        assert!(parser.process_line("more").value().is_pending());
+        assert_eq!(
+            parser.process_line("key2: value2"),
+            LineNumber::new(
+                3,
+                Output::Output(KeyValuePair {
+                    key: "key".to_string(),
+                    value: "value\nmore".to_string(),
+                })
+            )
+        );
+        assert_eq!(
+            parser.take_deferred_output(),
+            Some(LineNumber::new(
+                3,
+                Output::Output(KeyValuePair {
+                    key: "key2".to_string(),
+                    value: "value2".to_string(),
+                })
+            ))
+        );
+        assert_eq!(parser.take_deferred_output(), None);
+
+        // Aborting into a new multi-line value leaves nothing deferred.
+        assert!(parser
+            .process_line("key3: <text>value3")
+            .value()
+            .is_pending());
+        assert_eq!(
+            parser.process_line("key4: <text>value4").ok(),
+            Some(KeyValuePair {
+                key: "key3".to_string(),
+                value: "value3".to_string(),
+            })
+        );
+        assert_eq!(parser.take_deferred_output(), None);
+        assert_eq!(
+            parser.process_line("value4</text>").ok(),
+            Some(KeyValuePair {
+                key: "key4".to_string(),
+                value: "value4\nvalue4".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn length_limits() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default()
+            .with_max_key_bytes(4)
+            .with_max_value_line_bytes(12);
+        assert_eq!(parser.process_line("abcd: v").ok().unwrap(), ("abcd", "v"));
+        assert_eq!(
+            parser.process_line("abcde: v").into_inner(),
+            Output::KeylessLine("abcde: v".to_string())
+        );
+        assert_eq!(
+            parser.process_line("k: <text>abcdef").ok(),
+            None,
+            "12 byte value should be accepted"
+        );
+        assert_eq!(
+            parser.process_line("0123456789abc").into_inner(),
+            Output::KeylessLine("0123456789abc".to_string())
+        );
+        assert_eq!(
+            parser.process_line("0123</text>").ok().unwrap(),
+            ("k", "abcdef\n0123")
+        );
+        assert_eq!(
+            parser.process_line("k: <text>abcdefg").into_inner(),
+            Output::KeylessLine("k: <text>abcdefg".to_string())
+        );
+    }
+
+    #[test]
+    fn max_continuation_lines() {
+        let mut parser = KVParser::new(SPDXParsePolicy {}).with_max_continuation_lines(2);
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert!(parser.process_line("b").value().is_pending());
+        assert!(parser.would_finish("c"));
+        assert_eq!(parser.process_line("c").ok().unwrap(), ("key", "a\nb\nc"));
+        assert_eq!(
+            parser.process_line("d</text>").into_inner(),
+            Output::KeylessLine("d</text>".to_string())
+        );
+
+        // The count restarts with each value.
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert!(parser.process_line("b").value().is_pending());
+        assert_eq!(
+            parser.process_line("c</text>").ok().unwrap(),
+            ("key", "a\nb\nc")
+        );
+    }
+
+    #[test]
+    fn finish_unterminated() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        assert!(parser.process_line("a: b").ok().is_some());
+        assert_eq!(parser.finish(), None);
+        assert!(parser.process_line("key: <text>abc").value().is_pending());
+        let output = parser.finish().unwrap();
+        assert_eq!(output.line_number(), 2);
+        assert_eq!(
+            output.into_inner(),
+            Output::Error(ParseError::UnterminatedMultiline {
+                key: "key".to_string(),
+                value: "abc".to_string()
+            })
+        );
+        assert_eq!(parser.finish(), None);
+        assert_eq!(parser.pending_start_line(), None);
+
+        // take_pending_pair instead treats the first line as the whole value
+        assert!(parser.process_line("key: <text>abc").value().is_pending());
+        assert_eq!(parser.take_pending_pair().unwrap(), ("key", "abc"));
+    }
+
+    #[test]
+    fn finish_by_policy() {
+        let mut parser: KVParser<WhitespaceNormalizePolicy<IndentScopePolicy>> =
+            KVParser::default();
+        assert!(parser.process_line("key:").value().is_pending());
+        assert!(parser.process_line("  a   b").value().is_pending());
+        let output = parser.finish().unwrap();
+        assert_eq!(output.line_number(), 1);
+        assert_eq!(output.ok().unwrap(), ("key", "a b"));
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn on_line() {
+        let counts = Arc::new(Mutex::new([0; 3]));
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let callback_counts = Arc::clone(&counts);
+        parser.on_line(move |line, output| {
+            let index = match output {
+                Output::Output(_) => 0,
+                Output::Pending => 1,
+                _ => 2,
+            };
+            callback_counts.lock().unwrap()[index] += line;
+        });
+        for line in "a: b\n\nc: <text>d\ne</text>\nkeyless".lines() {
+            parser.process_line(line);
+        }
+        // Sums of the line numbers of each kind
+        assert_eq!(*counts.lock().unwrap(), [1 + 4, 3, 2 + 5]);
+    }
+
+    #[test]
+    fn on_line_deferred_and_finish() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut parser: KVParser<IndentScopePolicy> = KVParser::default();
+        let callback_seen = Arc::clone(&seen);
+        parser.on_line(move |line, output| {
+            if let Output::Output(pair) = output {
+                let seen = format!("{} {}: {}", line, pair.key, pair.value);
+                callback_seen.lock().unwrap().push(seen);
+            }
+        });
+        parser.process_line("a:");
+        parser.process_line("  x");
+        parser.process_line("b: 1");
+        parser.process_line_lazy("c:");
+        parser.process_line_lazy("  y");
+        parser.finish();
+        assert_eq!(*seen.lock().unwrap(), ["3 a: x", "3 b: 1", "4 c: y"]);
+    }
+
+    #[test]
+    fn whitespace_lint() {
+        let mut parser =
+            KVParser::new(TrimKeysPolicy::new(TrivialParsePolicy {})).with_whitespace_lint(true);
+        let input = "a: 1\n \tb: 2\nc : 3\n\t d\t: 4\n \tkeyless";
+        let pairs: Vec<_> = parser.lines(input).filter_map(|o| o.ok()).collect();
+        assert_eq!(pairs.len(), 4);
+        assert_eq!(pairs[2], ("c", "3"));
+        assert_eq!(
+            parser.take_lints(),
+            vec![
+                WhitespaceLint {
+                    line: 2,
+                    issue: WhitespaceIssue::MixedIndentation
+                },
+                WhitespaceLint {
+                    line: 3,
+                    issue: WhitespaceIssue::WhitespaceBeforeDelimiter
+                },
+                WhitespaceLint {
+                    line: 4,
+                    issue: WhitespaceIssue::MixedIndentation
+                },
+                WhitespaceLint {
+                    line: 4,
+                    issue: WhitespaceIssue::WhitespaceBeforeDelimiter
+                },
+            ]
+        );
+        assert!(parser.take_lints().is_empty());
+    }
+
+    #[test]
+    fn value_trim_chars() {
+        let mut parser = KVParser::new(SPDXParsePolicy {}).with_value_trim_chars(&['=', '*']);
+        assert_eq!(
+            parser.process_line("key: ===value===").ok().unwrap(),
+            ("key", "value")
+        );
+        assert_eq!(
+            parser.process_line("key: *=a=b=*").ok().unwrap(),
+            ("key", "a=b")
+        );
+        assert!(parser.process_line("key: <text>==a").value().is_pending());
+        assert_eq!(
+            parser.process_line("b==</text>").ok().unwrap(),
+            ("key", "a\nb")
+        );
+    }
+
+    #[test]
+    fn value_normalizer() {
+        let mut parser =
+            KVParser::new(SPDXParsePolicy {}).with_value_normalizer(Box::new(|value| {
+                if value.chars().any(char::is_uppercase) {
+                    value.to_lowercase().into()
+                } else {
+                    value.into()
+                }
+            }));
+        assert_eq!(parser.process_line("a: YES").ok().unwrap(), ("a", "yes"));
+        assert_eq!(parser.process_line("B: no").ok().unwrap(), ("B", "no"));
+        assert!(parser.process_line("c: <text>X").value().is_pending());
+        assert_eq!(parser.process_line("Y</text>").ok().unwrap(), ("c", "x\ny"));
+        assert!(parser.process_line("c: <text>X").value().is_pending());
+        assert_eq!(
+            parser.take_pending_pair_lazy().unwrap().value_lines(),
+            ["x"]
+        );
+    }
+
+    #[test]
+    fn strict() {
+        let mut parser = KVParser::new(SPDXParsePolicy {}).strict(true);
+        assert_eq!(
+            parser.process_line("no key here").into_inner(),
+            Output::Error(ParseError::UnexpectedKeylessLine("no key here".to_string()))
+        );
+        assert_eq!(parser.process_line("").into_inner(), Output::EmptyLine);
+        // Continuation lines have no key, but are accepted.
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert!(parser.process_line("no key here").value().is_pending());
+        assert_eq!(
+            parser.process_line("</text>").ok().unwrap(),
+            ("key", "a\nno key here\n")
+        );
+        assert!(parser
+            .process_line("no key")
+            .ok_or_err_on_keyless(())
+            .is_err());
+    }
+
+    #[test]
+    fn implicit_multiline_end() {
+        let mut parser = KVParser::new(SPDXParsePolicy {}).with_implicit_multiline_end(true);
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert!(parser.process_line("no key").value().is_pending());
+        assert!(parser.would_finish("key2: <text>b"));
+        assert_eq!(
+            parser.process_line("key2: <text>b").into_inner(),
+            Output::Output(KeyValuePair {
+                key: "key".to_string(),
+                value: "a\nno key".to_string(),
+            })
+        );
+        assert_eq!(parser.take_deferred_output(), None);
+        assert_eq!(parser.process_line("key3: c").ok().unwrap(), ("key2", "b"));
+        assert_eq!(
+            parser.take_deferred_output().unwrap().into_inner(),
+            Output::Output(KeyValuePair {
+                key: "key3".to_string(),
+                value: "c".to_string(),
+            })
+        );
+        // The policy finishing the value takes precedence.
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert_eq!(
+            parser.process_line("not: a key</text>").ok().unwrap(),
+            ("key", "a\nnot: a key")
+        );
+    }
+
+    #[test]
+    fn trim_value_lines() {
+        let parse_value = |mode: TrimMode| {
+            let mut parser = KVParser::new(SPDXParsePolicy {}).with_trim_value_lines(mode);
+            assert!(parser.process_line("key: <text> a ").value().is_pending());
+            assert!(parser.process_line("  b  ").value().is_pending());
+            parser.process_line(" c </text>").ok().unwrap().value
+        };
+        assert_eq!(parse_value(TrimMode::None), " a\n  b\n c ");
+        assert_eq!(parse_value(TrimMode::Trailing), " a\n  b\n c");
+        assert_eq!(parse_value(TrimMode::Both), "a\nb\nc");
+    }
+
+    #[test]
+    fn blank_lines_in_long_value() {
+        fn parse_value(lines: &[&str]) -> String {
+            let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+            let (last, rest) = lines.split_last().unwrap();
+            for line in rest {
+                assert!(parser.process_line(line).value().is_pending());
+            }
+            parser.process_line(last).ok().unwrap().value
+        }
+        // Runs of blank lines in the middle
+        assert_eq!(
+            parse_value(&["key: <text>a", "", "", "b</text>"]),
+            "a\n\n\nb"
+        );
+        assert_eq!(
+            parse_value(&["key: <text>a", "", "", "", "   ", "b</text>"]),
+            "a\n\n\n\n\nb"
+        );
+        // Leading blank lines, including the remainder of the opening line
+        assert_eq!(parse_value(&["key: <text>", "", "b</text>"]), "\n\nb");
+        // Trailing blank lines, including the start of the closing line
+        assert_eq!(parse_value(&["key: <text>a", "", "</text>"]), "a\n\n");
+        // Only blank lines
+        assert_eq!(parse_value(&["key: <text>", "", "</text>"]), "\n\n");
+    }
+
+    #[test]
+    fn lines_with_raw() {
+        let mut parser = KVParser::new(SPDXParsePolicy {});
+        let outputs: Vec<_> = parser
+            .lines_with_raw("a: 1\r\nb: <text>x\n y")
+            .map(|(raw, output)| (raw, output.into_inner()))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                (
+                    "a: 1",
+                    Output::Output(KeyValuePair {
+                        key: "a".to_string(),
+                        value: "1".to_string(),
+                    })
+                ),
+                ("b: <text>x", Output::Pending),
+                (" y", Output::Pending),
+                (
+                    "",
+                    Output::Error(ParseError::UnterminatedMultiline {
+                        key: "b".to_string(),
+                        value: "x\n y".to_string(),
+                    })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_str_keyless() {
+        let mut parser = KVParser::new(SPDXParsePolicy {});
+        let keyless: Vec<_> = parser
+            .parse_str_keyless("header\na: 1\n\nb: <text>not keyless\n</text>\nfooter")
+            .collect();
+        assert_eq!(
+            keyless,
+            vec![
+                LineNumber::new(1, "header".to_string()),
+                LineNumber::new(6, "footer".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_with_delimiters() {
+        let mut parser = KVParser::new(TrimKeysPolicy::new(SPDXParsePolicy {}));
+        let outputs: Vec<_> = parser
+            .lines_with_delimiters("a   :   1\nb: <text>x:\ny</text>\nc", ": ")
+            .filter(|(_, _, output)| output.value().is_some())
+            .map(|(raw, delimiter, _)| (raw, delimiter.unwrap()))
+            .collect();
+        assert_eq!(outputs.len(), 2);
+        let (raw, delimiter) = &outputs[0];
+        assert_eq!(delimiter.matched, "   :   ");
+        // Rewrite the value, keeping the original spacing
+        let rewritten = format!(
+            "{}{}{}",
+            &raw[..delimiter.byte_offset],
+            delimiter.matched,
+            "2"
+        );
+        assert_eq!(rewritten, "a   :   2");
+        assert_eq!(
+            outputs[1].1,
+            DelimiterInfo {
+                byte_offset: 1,
+                matched: ": ".to_string()
+            }
+        );
+        assert_eq!(DelimiterInfo::locate("no delimiter", ": "), None);
+        assert_eq!(
+            DelimiterInfo::locate("a:b: c", ": "),
+            Some(DelimiterInfo {
+                byte_offset: 3,
+                matched: ": ".to_string()
+            })
+        );
+
+        // Pairs packed on a line, including after the end of a multi-line value
+        let mut parser = KVParser::new(SPDXParsePolicy {});
+        let delimiters: Vec<_> = parser
+            .lines_with_delimiters(
+                "a: <text>x</text>bb:   y\nnote: <text>\nnote: x</text>key:  <text>start\nend</text>",
+                ": ",
+            )
+            .filter_map(|(_, delimiter, output)| Some((output.ok()?.key, delimiter?.byte_offset)))
+            .collect();
+        assert_eq!(
+            delimiters,
+            vec![
+                ("a".to_string(), 1),
+                ("bb".to_string(), 19),
+                ("note".to_string(), 4),
+                ("key".to_string(), 17),
+            ]
+        );
+
+        // An aborted value followed straight away by a new multi-line value
+        let mut parser = KVParser::new(AbortOnKeyPolicy {});
+        let delimiters: Vec<_> = parser
+            .lines_with_delimiters("a: <text>x\nb   :   <text>y\nz</text>", ": ")
+            .filter_map(|(_, delimiter, output)| Some((output.line_number(), delimiter?)))
+            .collect();
+        assert_eq!(
+            delimiters,
+            vec![
+                (
+                    2,
+                    DelimiterInfo {
+                        byte_offset: 1,
+                        matched: ": ".to_string()
+                    }
+                ),
+                (
+                    3,
+                    DelimiterInfo {
+                        byte_offset: 1,
+                        matched: "   :   ".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_results() {
+        let lines = vec![
+            Ok("key: value".to_string()),
+            Ok("key2: <text>a".to_string()),
+            Ok("b".to_string()),
+        ];
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let outputs: Result<Vec<_>, ()> = parser.parse_results(lines).collect();
+        let outputs = outputs.unwrap();
+        assert_eq!(outputs.len(), 4);
+        assert_eq!(outputs[0].value().clone().ok().unwrap(), ("key", "value"));
+        // The end of input is handled by KVParser::finish, numbered with the start of the value.
+        assert_eq!(
+            outputs[3],
+            LineNumber::new(
+                2,
+                Output::Error(ParseError::UnterminatedMultiline {
+                    key: "key2".to_string(),
+                    value: "a\nb".to_string(),
+                })
+            )
+        );
+
+        let lines = vec![
+            Ok("key: value".to_string()),
+            Err("broken"),
+            Ok("key2: value2".to_string()),
+        ];
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let mut results = parser.parse_results(lines);
+        assert!(results.next().unwrap().is_ok());
+        assert_eq!(results.next(), Some(Err("broken")));
+        assert_eq!(results.next(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parse_reader() {
+        let input = "key: value\r\nkey2: <text>a\n\nb";
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let mut outputs = vec![];
+        parser
+            .parse_reader(input.as_bytes(), |output| outputs.push(output))
+            .unwrap();
+        let line_numbers: Vec<_> = outputs.iter().map(|o| o.line_number()).collect();
+        assert_eq!(line_numbers, vec![1, 2, 3, 4, 2]);
+        assert_eq!(outputs[0].value().clone().ok().unwrap(), ("key", "value"));
+        assert_eq!(
+            *outputs[4].value(),
+            Output::Error(ParseError::UnterminatedMultiline {
+                key: "key2".to_string(),
+                value: "a\n\nb".to_string(),
+            })
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn line_reader_parser() {
+        let input = "key: value\r\nkey2: <text>a\n\nb";
+        let parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let outputs: Vec<_> = LineReaderParser::new(input.as_bytes(), parser)
+            .collect::<std::io::Result<_>>()
+            .unwrap();
+        let line_numbers: Vec<_> = outputs.iter().map(|o| o.line_number()).collect();
+        assert_eq!(line_numbers, vec![1, 2, 3, 4, 2]);
+        assert_eq!(outputs[0].value().clone().ok().unwrap(), ("key", "value"));
+        assert_eq!(
+            *outputs[4].value(),
+            Output::Error(ParseError::UnterminatedMultiline {
+                key: "key2".to_string(),
+                value: "a\n\nb".to_string(),
+            })
+        );
+
+        let mut iter =
+            LineReaderParser::new(&[0xffu8, b'\n'][..], KVParser::<SPDXParsePolicy>::default());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn trim_keys() {
+        let mut parser: KVParser<TrivialParsePolicy> = KVParser::default();
+        assert_eq!(
+            parser.process_line("\t key\t: value").ok().unwrap(),
+            ("\t key\t", "value")
+        );
+
+        let mut parser: KVParser<TrimKeysPolicy<SPDXParsePolicy>> = KVParser::default();
+        assert_eq!(
+            parser.process_line("\t key\t: value").ok().unwrap(),
+            ("key", "value")
+        );
+        assert_eq!(
+            parser
+                .process_line("  key2 : <text>value</text>")
+                .ok()
+                .unwrap(),
+            ("key2", "value")
+        );
+    }
+
+    #[test]
+    fn trim_key_trailing_before_delim() {
+        let mut parser =
+            KVParser::new(TrivialParsePolicy {}).with_trim_key_trailing_before_delim(true);
+        assert_eq!(
+            parser.process_line("key : value").ok().unwrap(),
+            ("key", "value")
+        );
+        assert_eq!(
+            parser.process_line("\t key\t: value").ok().unwrap(),
+            ("\t key", "value")
+        );
+
+        // General key trimming also removes the indentation.
+        let mut parser = KVParser::new(TrimKeysPolicy::new(TrivialParsePolicy {}))
+            .with_trim_key_trailing_before_delim(true);
+        assert_eq!(
+            parser.process_line("\t key\t: value").ok().unwrap(),
+            ("key", "value")
+        );
+    }
+
+    #[test]
+    fn lazy_value() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let pair = parser
+            .process_line_lazy("key: value")
+            .into_inner()
+            .ok()
+            .unwrap();
+        assert_eq!(pair.value_lines(), ["value"]);
+        assert_eq!(KeyValuePair::from(pair), ("key", "value"));
+
+        assert!(parser
+            .process_line_lazy("key: <text>a")
+            .value()
+            .is_pending());
+        assert!(parser.process_line_lazy("").value().is_pending());
+        let pair = parser
+            .process_line_lazy("b</text>")
+            .into_inner()
+            .ok()
+            .unwrap();
+        assert_eq!(pair.value_lines(), ["a", "", "b"]);
+        assert_eq!(pair.value(), "a\n\nb");
+
+        assert!(parser
+            .process_line_lazy("key: <text>a")
+            .value()
+            .is_pending());
+        let pair = parser.take_pending_pair_lazy().unwrap();
+        assert_eq!(pair.value_lines(), ["a"]);
+    }
+
+    #[test]
+    fn key_interning() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default().with_key_interning();
+        let first = parser
+            .process_line_interned("FileName: a")
+            .into_inner()
+            .ok()
+            .unwrap();
+        let second = parser
+            .process_line_interned("FileName: b")
+            .into_inner()
+            .ok()
+            .unwrap();
+        assert!(std::sync::Arc::ptr_eq(&first.key, &second.key));
+        assert_eq!(second.value, "b");
+        assert_eq!(KeyValuePair::from(first), ("FileName", "a"));
+
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let first = parser
+            .process_line_interned("FileName: a")
+            .into_inner()
+            .ok()
+            .unwrap();
+        let second = parser
+            .process_line_interned("FileName: b")
+            .into_inner()
+            .ok()
+            .unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&first.key, &second.key));
+    }
+
+    #[test]
+    fn lines() {
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let mut lines = parser.lines("key: value\r\n\nkey2: <text>a\nb");
+        assert_eq!(lines.size_hint().0, 4);
+        assert_eq!(
+            lines.next(),
+            Some(LineNumber::new(
+                1,
+                Output::Output(KeyValuePair {
+                    key: "key".to_string(),
+                    value: "value".to_string(),
+                })
+            ))
+        );
+        assert_eq!(lines.size_hint(), (3, None));
+        let rest: Vec<_> = lines.collect();
+        assert_eq!(rest.len(), 4);
+        assert_eq!(rest[0], LineNumber::new(2, Output::EmptyLine));
+        assert_eq!(rest[2], LineNumber::new(4, Output::Pending));
+        assert_eq!(
+            rest[3],
+            LineNumber::new(
+                3,
+                Output::Error(ParseError::UnterminatedMultiline {
+                    key: "key2".to_string(),
+                    value: "a\nb".to_string(),
+                })
+            )
+        );
+
+        assert_eq!(parser.lines("").count(), 0);
+        assert_eq!(parser.lines("a: b\n").count(), 1);
+
+        // A packed line produces more outputs than lines.
+        let packed = "a: <text>1</text>b: <text>2</text>c: <text>3</text>d: <text>4</text>e: 5";
+        assert_eq!(parser.lines(packed).size_hint(), (1, None));
+        assert_eq!(parser.lines(packed).count(), 5);
+    }
 }
@@ -6,21 +6,81 @@
 //!
 //! [KVParser]: crate::KVParser
 
-use super::parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue};
+use std::borrow::Cow;
+
+use super::parse_policy::{
+    InnerState, ParsePolicy, PolicyWrapper, ProcessedContinuationValue, ProcessedValue,
+};
+use crate::ParsedLine;
+
+mod append_aware;
+mod block_scalar;
+mod define;
+mod escaped_delimiter;
+mod flag;
+mod indent_scope;
+mod properties;
+mod smart_trim;
+mod spdx_escaped_text;
+mod spdx_inline_comment;
+mod trailing_colon;
+mod trailing_comma;
+
+pub use append_aware::{AppendAwarePolicy, KeyValueOp, ValueOp};
+pub use block_scalar::{BlockScalarPolicy, BlockScalarState};
+pub use define::DefinePolicy;
+pub use escaped_delimiter::EscapedDelimiterPolicy;
+pub use flag::{is_flag_token, FlagPolicy, FLAG_VALUE};
+pub use indent_scope::{IndentScopePolicy, IndentScopeState};
+pub use properties::PropertiesPolicy;
+pub use smart_trim::SmartTrimPolicy;
+pub use spdx_escaped_text::SPDXEscapedTextPolicy;
+pub use spdx_inline_comment::SPDXInlineCommentPolicy;
+pub use trailing_colon::TrailingColonPolicy;
+pub use trailing_comma::TrailingCommaPolicy;
 
 pub const TEXT_OPEN_TAG: &str = "<text>";
 pub const TEXT_CLOSE_TAG: &str = "</text>";
+/// The escaped form of [TEXT_CLOSE_TAG], for a literal `</text>` within a `<text>` value,
+/// as used by [SPDXEscapedTextPolicy].
+pub const ESCAPED_TEXT_CLOSE_TAG: &str = "<\\/text>";
+
+/// Whether [SPDXParsePolicy] needs `<text>` tags to reproduce a value.
+fn needs_text_tags(value: &str) -> bool {
+    value.contains('\n') || value.trim() != value || value.starts_with(TEXT_OPEN_TAG)
+}
+
+/// Wrap a value in `<text>` tags, if [SPDXParsePolicy] reproduces it from between them:
+/// the end of each line but the last is trimmed, and may end the value with `</text>`.
+fn wrap_in_text_tags(value: &str) -> Option<Cow<'_, str>> {
+    let mut lines = value.split('\n');
+    lines.next_back();
+    if lines.any(|line| line.trim_end() != line || line.contains(TEXT_CLOSE_TAG)) {
+        return None;
+    }
+    Some(Cow::Owned(format!(
+        "{}{}{}",
+        TEXT_OPEN_TAG, value, TEXT_CLOSE_TAG
+    )))
+}
 
 #[derive(Debug, Default, Clone, Copy)]
 /// The simplest parse policy, that does no trimming or transformation, and no multi-line values.
 pub struct TrivialParsePolicy {}
 impl ParsePolicy for TrivialParsePolicy {
-    fn process_value<'a>(&self, _key: &str, value: &'a str) -> ProcessedValue<'a> {
-        ProcessedValue::CompleteValue(value)
+    type State = ();
+
+    fn never_multiline(&self) -> bool {
+        true
+    }
+
+    fn process_value<'a>(&self, _state: &mut (), _key: &str, value: &'a str) -> ProcessedValue<'a> {
+        ProcessedValue::CompleteValue(value.into())
     }
 
     fn process_continuation<'a>(
         &self,
+        _state: &mut (),
         _key: &str,
         _continuation_line: &'a str,
     ) -> ProcessedContinuationValue<'a> {
@@ -28,37 +88,412 @@ impl ParsePolicy for TrivialParsePolicy {
     }
 }
 
+/// Split a line at `</text>` followed by a key: value pair, returning the text before it and the pair.
+fn split_packed(line: &str) -> Option<(&str, &str)> {
+    line.split_once(TEXT_CLOSE_TAG)
+        .map(|(value, rest)| (value, rest.trim_start()))
+        .filter(|(_, rest)| matches!(ParsedLine::from(*rest), ParsedLine::Pair(_)))
+}
+
 #[derive(Debug, Default, Clone, Copy)]
 /// The parse policy used for SPDX Tag-Value files, where a value that starts with `<text>` continues
 /// possibly across multiple lines until `</text>`, both of which are trimmed.
+///
+/// Blank lines within the value are preserved, including any immediately after `<text>`
+/// or before `</text>`, so the lines between the tags are reproduced exactly
+/// (apart from trailing whitespace on each line).
+///
+/// A line with `</text>` followed by a key: value pair, such as `end</text>key: value`
+/// or `key: <text>value</text>key2: value2`, ends the value and starts the next pair.
 pub struct SPDXParsePolicy {}
 impl ParsePolicy for SPDXParsePolicy {
-    fn process_value<'a>(&self, _key: &str, value: &'a str) -> ProcessedValue<'a> {
+    type State = ();
+
+    /// Wraps values in `<text>` and `</text>` if they span multiple lines,
+    /// have surrounding whitespace, or start with `<text>`.
+    ///
+    /// Returns `None` for a multi-line value with trailing whitespace
+    /// or `</text>` on any line but the last, since they would not be reproduced.
+    fn wrap_value<'a>(&self, _key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        if needs_text_tags(value) {
+            wrap_in_text_tags(value)
+        } else {
+            Some(Cow::Borrowed(value))
+        }
+    }
+
+    fn process_value<'a>(&self, _state: &mut (), _key: &str, value: &'a str) -> ProcessedValue<'a> {
         let trimmed_val = value.trim();
         if let Some(value) = trimmed_val.strip_prefix(TEXT_OPEN_TAG) {
             if let Some(value) = value.strip_suffix(TEXT_CLOSE_TAG) {
                 // found both open and close
-                ProcessedValue::CompleteValue(value)
+                ProcessedValue::CompleteValue(value.into())
+            } else if let Some((value, rest)) = split_packed(value) {
+                // A packed line like `key: <text>value</text>key2: value2`
+                ProcessedValue::CompleteValueAndReprocessRest(value.into(), rest)
             } else {
                 // only found open
-                ProcessedValue::StartOfMultiline(Some(value))
+                ProcessedValue::StartOfMultiline(Some(value.into()))
             }
         } else {
             // just plain text
-            ProcessedValue::CompleteValue(value)
+            ProcessedValue::CompleteValue(value.into())
         }
     }
 
     fn process_continuation<'a>(
         &self,
+        _state: &mut (),
         _key: &str,
         continuation_line: &'a str,
     ) -> ProcessedContinuationValue<'a> {
         let line = continuation_line.trim_end();
         if let Some(stripped) = line.strip_suffix(TEXT_CLOSE_TAG) {
-            ProcessedContinuationValue::FinishMultiline(Some(stripped))
+            ProcessedContinuationValue::FinishMultiline(Some(stripped.into()))
+        } else if let Some((value, rest)) = split_packed(line) {
+            // A packed line like `end</text>key: value`
+            ProcessedContinuationValue::FinishAndReprocessRest(Some(value.into()), rest)
         } else {
-            ProcessedContinuationValue::ContinueMultiline(Some(line))
+            ProcessedContinuationValue::ContinueMultiline(Some(line.into()))
+        }
+    }
+}
+
+/// Trim a value and collapse internal runs of whitespace to a single space,
+/// only allocating if the value changes beyond trimming.
+fn normalize_whitespace(value: &str) -> Cow<'_, str> {
+    let trimmed = value.trim();
+    let mut prev_was_whitespace = false;
+    let is_normalized = trimmed.chars().all(|c| {
+        let ok = !c.is_whitespace() || (c == ' ' && !prev_was_whitespace);
+        prev_was_whitespace = c.is_whitespace();
+        ok
+    });
+    if is_normalized {
+        Cow::Borrowed(trimmed)
+    } else {
+        Cow::Owned(trimmed.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+fn normalize_whitespace_cow(value: Cow<'_, str>) -> Cow<'_, str> {
+    match value {
+        Cow::Borrowed(value) => normalize_whitespace(value),
+        Cow::Owned(value) => Cow::Owned(normalize_whitespace(&value).into_owned()),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy wrapping another, that trims each value (or line of a multi-line value)
+/// and collapses internal runs of whitespace to a single space.
+///
+/// Useful for fuzzy matching of values.
+pub struct WhitespaceNormalizePolicy<P = TrivialParsePolicy> {
+    inner: P,
+}
+
+impl<P: ParsePolicy> WhitespaceNormalizePolicy<P> {
+    /// Wrap a policy, normalizing the whitespace in the values it returns.
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+/// Transform the value of a processed continuation line, if any.
+fn map_continuation<'a>(
+    value: ProcessedContinuationValue<'a>,
+    f: impl Fn(Cow<'a, str>) -> Cow<'a, str>,
+) -> ProcessedContinuationValue<'a> {
+    match value {
+        ProcessedContinuationValue::ContinueMultiline(value) => {
+            ProcessedContinuationValue::ContinueMultiline(value.map(f))
+        }
+        ProcessedContinuationValue::FinishMultiline(value) => {
+            ProcessedContinuationValue::FinishMultiline(value.map(f))
+        }
+        ProcessedContinuationValue::AppendToPrevious(value) => {
+            ProcessedContinuationValue::AppendToPrevious(f(value))
+        }
+        ProcessedContinuationValue::FinishAppendToPrevious(value) => {
+            ProcessedContinuationValue::FinishAppendToPrevious(f(value))
+        }
+        ProcessedContinuationValue::FinishAndReprocessRest(value, rest) => {
+            ProcessedContinuationValue::FinishAndReprocessRest(value.map(f), rest)
+        }
+        ProcessedContinuationValue::AbortAndReprocess => {
+            ProcessedContinuationValue::AbortAndReprocess
+        }
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for WhitespaceNormalizePolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        match self.inner.process_value(state, key, value) {
+            ProcessedValue::CompleteValue(value) => {
+                ProcessedValue::CompleteValue(normalize_whitespace_cow(value))
+            }
+            ProcessedValue::StartOfMultiline(value) => {
+                ProcessedValue::StartOfMultiline(value.map(normalize_whitespace_cow))
+            }
+            ProcessedValue::CompleteValueAndReprocessRest(value, rest) => {
+                ProcessedValue::CompleteValueAndReprocessRest(normalize_whitespace_cow(value), rest)
+            }
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        map_continuation(
+            self.inner
+                .process_continuation(state, key, continuation_line),
+            normalize_whitespace_cow,
+        )
+    }
+
+    fn finish<'a>(&self, state: &mut InnerState<Self>) -> Option<ProcessedContinuationValue<'a>> {
+        self.inner
+            .finish(state)
+            .map(|value| map_continuation(value, normalize_whitespace_cow))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy wrapping another, that trims whitespace from the start and end of keys.
+///
+/// See [ParsePolicy::trim_keys].
+pub struct TrimKeysPolicy<P> {
+    inner: P,
+}
+
+impl<P: ParsePolicy> TrimKeysPolicy<P> {
+    /// Wrap a policy, trimming the keys passed to it.
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for TrimKeysPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn trim_keys(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy wrapping another, that converts ASCII letters in keys to lowercase,
+/// before the keys are passed to the inner policy.
+///
+/// Useful for formats with case-insensitive keys.
+pub struct LowercaseKeyPolicy<P> {
+    inner: P,
+}
+
+impl<P: ParsePolicy> LowercaseKeyPolicy<P> {
+    /// Wrap a policy, lowercasing the keys passed to it.
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for LowercaseKeyPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn parse_line(&self, state: &mut InnerState<Self>, line: &str) -> ParsedLine {
+        match self.inner.parse_line(state, line) {
+            ParsedLine::Pair(mut pair) => {
+                pair.key.make_ascii_lowercase();
+                ParsedLine::Pair(pair)
+            }
+            parsed => parsed,
         }
     }
 }
+
+#[derive(Debug, Clone)]
+/// A parse policy wrapping another, that treats lines starting with a marker
+/// (after any leading whitespace) as comments.
+///
+/// See [ParsePolicy::is_comment].
+pub struct CommentPolicy<P = TrivialParsePolicy> {
+    inner: P,
+    marker: String,
+}
+
+impl<P: ParsePolicy> CommentPolicy<P> {
+    /// Wrap a policy, treating lines starting with `marker` as comments.
+    pub fn new(inner: P, marker: impl Into<String>) -> Self {
+        Self {
+            inner,
+            marker: marker.into(),
+        }
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for CommentPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn is_comment(&self, line: &str) -> bool {
+        line.trim_start().starts_with(self.marker.as_str()) || self.inner.is_comment(line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use super::{
+        normalize_whitespace, CommentPolicy, LowercaseKeyPolicy, SPDXParsePolicy,
+        TrivialParsePolicy, WhitespaceNormalizePolicy,
+    };
+    use crate::{
+        parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+        KVParser, Output, ParserOutput,
+    };
+
+    #[test]
+    fn bundled_policies_borrow() {
+        assert!(matches!(
+            TrivialParsePolicy {}.process_value(&mut (), "key", " value "),
+            ProcessedValue::CompleteValue(Cow::Borrowed(" value "))
+        ));
+        let policy = SPDXParsePolicy {};
+        assert!(matches!(
+            policy.process_value(&mut (), "key", "<text>value</text>"),
+            ProcessedValue::CompleteValue(Cow::Borrowed("value"))
+        ));
+        assert!(matches!(
+            policy.process_value(&mut (), "key", "<text>value"),
+            ProcessedValue::StartOfMultiline(Some(Cow::Borrowed("value")))
+        ));
+        assert!(matches!(
+            policy.process_continuation(&mut (), "key", "value"),
+            ProcessedContinuationValue::ContinueMultiline(Some(Cow::Borrowed("value")))
+        ));
+        assert!(matches!(
+            policy.process_continuation(&mut (), "key", "value</text>"),
+            ProcessedContinuationValue::FinishMultiline(Some(Cow::Borrowed("value")))
+        ));
+    }
+
+    #[test]
+    fn spdx_packed_lines() {
+        let input = "\
+a: <text>x
+prev</text>key: <text>next
+more</text> c: d
+e: <text>f</text>g: h
+i: <text>j</text>k: <text>l</text>m: <text>n
+o</text>
+p: <text>q</text>not a pair
+r</text>";
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let outputs: Vec<_> = parser
+            .lines(input)
+            .filter(|o| o.value().is_some())
+            .map(|o| o.line_number())
+            .collect();
+        assert_eq!(outputs, vec![2, 3, 3, 4, 4, 5, 5, 6, 8]);
+
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        let pairs: Vec<_> = parser.lines(input).filter_map(|o| o.ok()).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a", "x\nprev"),
+                ("key", "next\nmore"),
+                ("c", "d"),
+                ("e", "f"),
+                ("g", "h"),
+                ("i", "j"),
+                ("k", "l"),
+                ("m", "n\no"),
+                ("p", "q</text>not a pair\nr"),
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_normalize() {
+        assert!(matches!(
+            normalize_whitespace("  a b c "),
+            Cow::Borrowed("a b c")
+        ));
+        assert_eq!(normalize_whitespace("a  b\tc"), "a b c");
+        assert_eq!(normalize_whitespace(" \t "), "");
+
+        let mut parser: KVParser<WhitespaceNormalizePolicy> = KVParser::default();
+        assert_eq!(
+            parser.process_line("key:   a \t b  ").ok().unwrap(),
+            ("key", "a b")
+        );
+
+        let mut parser = KVParser::new(WhitespaceNormalizePolicy::new(SPDXParsePolicy {}));
+        assert!(parser.process_line("key: <text> a  b").ok().is_none());
+        assert_eq!(
+            parser.process_line("c   d </text>").ok().unwrap(),
+            ("key", "a b\nc d")
+        );
+    }
+
+    #[test]
+    fn lowercase_keys() {
+        let mut parser = KVParser::new(LowercaseKeyPolicy::new(SPDXParsePolicy {}));
+        assert_eq!(
+            parser.process_line("PackageName: Value").ok().unwrap(),
+            ("packagename", "Value")
+        );
+        assert_eq!(parser.process_line("ÄB: c").ok().unwrap(), ("Äb", "c"));
+    }
+
+    #[test]
+    fn comments() {
+        let mut parser = KVParser::new(CommentPolicy::new(SPDXParsePolicy {}, "##"));
+        assert_eq!(
+            parser.process_line("  ## a: comment").into_inner(),
+            Output::Comment("  ## a: comment".to_string())
+        );
+        assert_eq!(
+            parser.process_line("# not: a comment").ok().unwrap(),
+            ("# not", "a comment")
+        );
+        // Comment markers are not special within a multi-line value.
+        assert!(parser.process_line("key: <text>a").value().is_pending());
+        assert!(parser.process_line("## b").value().is_pending());
+        assert_eq!(
+            parser.process_line("</text>").ok().unwrap(),
+            ("key", "a\n## b\n")
+        );
+        assert_eq!(
+            parser.process_line("## c").ok_or_err_on_keyless(()),
+            Ok(None)
+        );
+    }
+}
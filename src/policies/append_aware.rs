@@ -0,0 +1,119 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    KVParser, KeyValuePair, LineNumber, Output, ParsedLine,
+};
+
+/// Whether a [KeyValueOp] sets or appends to a key.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ValueOp {
+    /// `key=value`: replace any existing values of the key.
+    #[default]
+    Set,
+    /// `key+=value`: add a value for the key, keeping any existing values.
+    Append,
+}
+
+/// A key-value pair along with the operation it performs, as parsed with [AppendAwarePolicy].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValueOp {
+    pub key: String,
+    pub value: String,
+    pub op: ValueOp,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy for config-override files, where `key=value` sets a key
+/// and `key+=value` appends to it.
+///
+/// Lines are split on the first `=`, which is an append if immediately preceded by `+`,
+/// and keys and values are trimmed.
+/// The operation of the most recent line is the [ParsePolicy::State] of this policy:
+/// use [KVParser::process_line_op] to get pairs along with their operations,
+/// and apply them with [Record::apply_ops](crate::record::Record::apply_ops).
+/// There are no multi-line values.
+pub struct AppendAwarePolicy {}
+
+impl ParsePolicy for AppendAwarePolicy {
+    type State = ValueOp;
+
+    fn parse_line(&self, state: &mut ValueOp, line: &str) -> ParsedLine {
+        match ParsedLine::split_on(line, "=") {
+            ParsedLine::Pair(pair) => {
+                let (key, op) = match pair.key.strip_suffix('+') {
+                    Some(key) => (key, ValueOp::Append),
+                    None => (pair.key.as_str(), ValueOp::Set),
+                };
+                *state = op;
+                ParsedLine::Pair(KeyValuePair {
+                    key: key.trim().to_string(),
+                    value: pair.value.trim().to_string(),
+                })
+            }
+            parsed => {
+                *state = ValueOp::Set;
+                parsed
+            }
+        }
+    }
+
+    fn never_multiline(&self) -> bool {
+        true
+    }
+
+    fn process_value<'a>(
+        &self,
+        _state: &mut ValueOp,
+        _key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        ProcessedValue::CompleteValue(value.into())
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        _state: &mut ValueOp,
+        _key: &str,
+        _continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        unreachable!()
+    }
+}
+
+impl KVParser<AppendAwarePolicy> {
+    /// Like [KVParser::process_line], but returns each pair along with its operation.
+    pub fn process_line_op(&mut self, line: &str) -> LineNumber<Output<KeyValueOp>> {
+        let output = self.process_line(line);
+        let op = *self.policy_state();
+        output.map(|output| output.map(|KeyValuePair { key, value }| KeyValueOp { key, value, op }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AppendAwarePolicy, KeyValueOp, ValueOp};
+    use crate::{KVParser, ParserOutput};
+
+    fn parse(line: &str) -> KeyValueOp {
+        let mut parser: KVParser<AppendAwarePolicy> = KVParser::default();
+        parser.process_line_op(line).ok().unwrap()
+    }
+
+    #[test]
+    fn append_aware() {
+        let op = |key: &str, value: &str, op| KeyValueOp {
+            key: key.to_string(),
+            value: value.to_string(),
+            op,
+        };
+        assert_eq!(parse("a=1"), op("a", "1", ValueOp::Set));
+        assert_eq!(parse(" a = 1 "), op("a", "1", ValueOp::Set));
+        assert_eq!(parse("a+=1"), op("a", "1", ValueOp::Append));
+        assert_eq!(parse("a += x=y"), op("a", "x=y", ValueOp::Append));
+        assert_eq!(parse("a=+1"), op("a", "+1", ValueOp::Set));
+        assert_eq!(parse("c+ = 1"), op("c+", "1", ValueOp::Set));
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use super::IndentScopeState;
+use crate::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    ParsedLine,
+};
+
+/// How the lines of a block scalar are joined.
+#[derive(Debug, Default, Clone, Copy)]
+enum BlockStyle {
+    /// `|`: lines are joined with newlines.
+    #[default]
+    Literal,
+    /// `>`: lines are joined with spaces, and empty lines separate paragraphs.
+    Folded,
+}
+
+#[derive(Debug, Default, Clone)]
+/// A parse policy for values written as YAML-style block scalars.
+///
+/// Values are scoped by indentation as for [IndentScopePolicy](super::IndentScopePolicy),
+/// but are started by a value of just `|` (literal) or `>` (folded) rather than an empty value.
+/// Literal lines are joined with `\n`, keeping empty lines.
+/// Folded lines are joined with spaces, and each run of empty lines becomes a single `\n`.
+/// Chomping and indentation indicators, such as `|-`, are not supported.
+pub struct BlockScalarPolicy;
+
+/// The [ParsePolicy::State] of [BlockScalarPolicy]: the style and indentation of the current value.
+#[derive(Debug, Default, Clone)]
+pub struct BlockScalarState {
+    scope: IndentScopeState,
+    style: BlockStyle,
+    /// In a folded value, whether the next line starts a new line rather than being appended.
+    fold_new_line: bool,
+}
+
+impl ParsePolicy for BlockScalarPolicy {
+    type State = BlockScalarState;
+
+    fn parse_line(&self, state: &mut BlockScalarState, line: &str) -> ParsedLine {
+        ParsedLine::from(state.scope.strip_line_indent(line))
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut BlockScalarState,
+        _key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        let style = match value.trim() {
+            "|" => BlockStyle::Literal,
+            ">" => BlockStyle::Folded,
+            _ => return ProcessedValue::CompleteValue(value.into()),
+        };
+        state.style = style;
+        state.scope.start_scope();
+        state.fold_new_line = true;
+        ProcessedValue::StartOfMultiline(None)
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut BlockScalarState,
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        let style = state.style;
+        if continuation_line.trim().is_empty() {
+            return match style {
+                BlockStyle::Literal => {
+                    ProcessedContinuationValue::ContinueMultiline(Some("".into()))
+                }
+                BlockStyle::Folded => {
+                    state.fold_new_line = true;
+                    ProcessedContinuationValue::ContinueMultiline(None)
+                }
+            };
+        }
+        let line = match state.scope.dedent(continuation_line) {
+            Some(line) => line,
+            None => return ProcessedContinuationValue::AbortAndReprocess,
+        };
+        match style {
+            BlockStyle::Literal => ProcessedContinuationValue::ContinueMultiline(Some(line.into())),
+            BlockStyle::Folded if core::mem::replace(&mut state.fold_new_line, false) => {
+                ProcessedContinuationValue::ContinueMultiline(Some(line.into()))
+            }
+            BlockStyle::Folded => {
+                ProcessedContinuationValue::AppendToPrevious(Cow::Owned(format!(" {}", line)))
+            }
+        }
+    }
+
+    fn finish<'a>(&self, _state: &mut BlockScalarState) -> Option<ProcessedContinuationValue<'a>> {
+        Some(ProcessedContinuationValue::FinishMultiline(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BlockScalarPolicy;
+    use crate::{KVParser, ParserOutput};
+
+    #[test]
+    fn block_scalar() {
+        let mut parser: KVParser<BlockScalarPolicy> = KVParser::default();
+        let input = "\
+a: 1
+literal: |
+  first
+    nested
+
+  last
+folded: >
+  one
+  two
+
+  three
+b: |x
+c: |
+  trailing";
+        let pairs: Vec<_> = parser.lines(input).filter_map(|o| o.ok()).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a", "1"),
+                ("literal", "first\n  nested\n\nlast"),
+                ("folded", "one two\nthree"),
+                ("b", "|x"),
+                ("c", "trailing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn would_finish_keeps_state() {
+        let mut parser: KVParser<BlockScalarPolicy> = KVParser::default();
+        let mut pairs = Vec::new();
+        for line in ["k: >", "  one", "  two", "x: 1"] {
+            let _ = parser.would_finish(line);
+            pairs.extend(parser.process_line(line).ok());
+            pairs.extend(parser.take_deferred_output().and_then(|o| o.ok()));
+        }
+        assert_eq!(pairs, vec![("k", "one two"), ("x", "1")]);
+    }
+}
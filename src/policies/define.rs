@@ -0,0 +1,159 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use crate::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    KeyValuePair, ParsedLine,
+};
+
+#[derive(Debug, Default, Clone)]
+/// A parse policy for C preprocessor definitions, such as `#define VERSION 3`.
+///
+/// The key is the macro name, including the parameter list for function-like macros,
+/// so `#define MAX(a, b) ((a) > (b) ? (a) : (b))` has the key `MAX(a, b)`.
+/// The value is the rest of the line, trimmed, and is empty for a bare `#define FLAG`.
+/// A value ending in `\` continues on the next line, joined with a space.
+/// Whitespace is allowed before and after the `#`, and all other lines are keyless.
+///
+/// The [ParsePolicy::State] of this policy is whether the value so far is non-empty.
+pub struct DefinePolicy;
+
+/// Split a definition after `#define` into the macro name (with any parameters) and the rest.
+fn split_definition(definition: &str) -> Option<(&str, &str)> {
+    let name_end = definition
+        .find(|c: char| c.is_whitespace() || c == '(')
+        .unwrap_or(definition.len());
+    if name_end == 0 {
+        return None;
+    }
+    // A function-like macro has its parameter list immediately after the name.
+    let key_end = if definition[name_end..].starts_with('(') {
+        name_end + definition[name_end..].find(')')? + 1
+    } else {
+        name_end
+    };
+    Some((&definition[..key_end], &definition[key_end..]))
+}
+
+/// Split off a trailing `\`, returning the trimmed rest and whether it was found.
+fn strip_continuation(value: &str) -> (&str, bool) {
+    match value.trim().strip_suffix('\\') {
+        Some(value) => (value.trim_end(), true),
+        None => (value.trim(), false),
+    }
+}
+
+impl ParsePolicy for DefinePolicy {
+    type State = bool;
+
+    fn parse_line(&self, _state: &mut bool, line: &str) -> ParsedLine {
+        if line.trim().is_empty() {
+            return ParsedLine::EmptyLine;
+        }
+        let definition = line
+            .trim_start()
+            .strip_prefix('#')
+            .map(str::trim_start)
+            .and_then(|directive| directive.strip_prefix("define"))
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+            .and_then(|rest| split_definition(rest.trim_start()));
+        match definition {
+            Some((key, value)) => ParsedLine::Pair(KeyValuePair {
+                key: key.to_string(),
+                value: value.to_string(),
+            }),
+            None => ParsedLine::KeylessLine(line.to_string()),
+        }
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut bool,
+        _key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        match strip_continuation(value) {
+            (value, true) => {
+                *state = !value.is_empty();
+                ProcessedValue::StartOfMultiline(Some(value.into()))
+            }
+            (value, false) => ProcessedValue::CompleteValue(value.into()),
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut bool,
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        let (line, continues) = strip_continuation(continuation_line);
+        let line = if line.is_empty() || !core::mem::replace(state, true) {
+            Cow::Borrowed(line)
+        } else {
+            Cow::Owned(format!(" {}", line))
+        };
+        if continues {
+            ProcessedContinuationValue::AppendToPrevious(line)
+        } else {
+            ProcessedContinuationValue::FinishAppendToPrevious(line)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DefinePolicy;
+    use crate::{KVParser, Output, ParserOutput};
+
+    #[test]
+    fn define() {
+        let mut parser: KVParser<DefinePolicy> = KVParser::default();
+        assert_eq!(
+            parser.process_line("#define VERSION 3").ok().unwrap(),
+            ("VERSION", "3")
+        );
+        assert_eq!(
+            parser
+                .process_line("  #  define\tNAME  \"a b\" ")
+                .ok()
+                .unwrap(),
+            ("NAME", "\"a b\"")
+        );
+        assert_eq!(
+            parser.process_line("#define FLAG").ok().unwrap(),
+            ("FLAG", "")
+        );
+        assert_eq!(
+            parser
+                .process_line("#define MAX(a, b) ((a) > (b) ? (a) : (b))")
+                .ok()
+                .unwrap(),
+            ("MAX(a, b)", "((a) > (b) ? (a) : (b))")
+        );
+        // Not function-like, since the parenthesis does not follow the name immediately.
+        assert_eq!(
+            parser.process_line("#define NEG (-1)").ok().unwrap(),
+            ("NEG", "(-1)")
+        );
+        for line in ["#include <stdio.h>", "#defined X", "#define", "int x;"] {
+            assert_eq!(
+                parser.process_line(line).into_inner(),
+                Output::KeylessLine(line.to_string())
+            );
+        }
+
+        assert!(parser
+            .process_line("#define SUM(a) \\")
+            .value()
+            .is_pending());
+        assert!(parser.process_line("    (a) + \\").value().is_pending());
+        assert_eq!(
+            parser.process_line("    1").ok().unwrap(),
+            ("SUM(a)", "(a) + 1")
+        );
+    }
+}
@@ -0,0 +1,119 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use crate::{
+    parse_policy::{InnerState, ParsePolicy, PolicyWrapper},
+    parsed_line::DELIM,
+    KeyValuePair, ParsedLine,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy wrapping another, that allows keys to contain `: ` by escaping the colon as `\:`.
+///
+/// Lines are split on the first `: ` whose colon is not escaped by a backslash,
+/// and `\:` and `\\` are unescaped in the key.
+/// Values are passed to the inner policy unchanged, since only the first delimiter splits the line.
+pub struct EscapedDelimiterPolicy<P> {
+    inner: P,
+}
+
+impl<P: ParsePolicy> EscapedDelimiterPolicy<P> {
+    /// Wrap a policy, splitting lines on unescaped delimiters.
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+/// Find the byte offset of the first delimiter not preceded by an odd number of backslashes.
+fn find_unescaped_delimiter(line: &str) -> Option<usize> {
+    line.match_indices(DELIM)
+        .map(|(i, _)| i)
+        .find(|&i| line[..i].bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 0)
+}
+
+/// Replace `\:` with `:` and `\\` with `\`, only allocating if there are any.
+fn unescape_key(key: &str) -> Cow<'_, str> {
+    if !key.contains('\\') {
+        return Cow::Borrowed(key);
+    }
+    let mut out = String::with_capacity(key.len());
+    let mut chars = key.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ (':' | '\\')) => out.push(escaped),
+                Some(other) => {
+                    out.push('\\');
+                    out.push(other);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+impl<P: ParsePolicy> PolicyWrapper for EscapedDelimiterPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn parse_line(&self, _state: &mut InnerState<Self>, line: &str) -> ParsedLine {
+        if line.trim().is_empty() {
+            return ParsedLine::EmptyLine;
+        }
+        match find_unescaped_delimiter(line) {
+            Some(delim) => ParsedLine::Pair(KeyValuePair {
+                key: unescape_key(&line[..delim]).into_owned(),
+                value: line[delim + DELIM.len()..].to_string(),
+            }),
+            None => ParsedLine::KeylessLine(line.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EscapedDelimiterPolicy;
+    use crate::{policies::TrivialParsePolicy, KVParser, Output, ParserOutput};
+
+    #[test]
+    fn escaped_delimiters() {
+        let mut parser: KVParser<EscapedDelimiterPolicy<TrivialParsePolicy>> = KVParser::default();
+        assert_eq!(
+            parser.process_line("key: value: more").ok().unwrap(),
+            ("key", "value: more")
+        );
+        assert_eq!(
+            parser.process_line("key\\: still_key: value").ok().unwrap(),
+            ("key: still_key", "value")
+        );
+        // A doubled backslash is an escaped backslash, so the delimiter is not escaped.
+        assert_eq!(
+            parser.process_line("key\\\\: value").ok().unwrap(),
+            ("key\\", "value")
+        );
+        assert_eq!(
+            parser
+                .process_line("key\\\\\\: still_key: value")
+                .ok()
+                .unwrap(),
+            ("key\\: still_key", "value")
+        );
+        assert_eq!(
+            parser.process_line("path\\to: value").ok().unwrap(),
+            ("path\\to", "value")
+        );
+        assert_eq!(
+            parser.process_line("only\\: escaped").into_inner(),
+            Output::KeylessLine("only\\: escaped".to_string())
+        );
+        assert_eq!(parser.process_line("  ").into_inner(), Output::EmptyLine);
+    }
+}
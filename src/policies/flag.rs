@@ -0,0 +1,103 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::{
+    parse_policy::{InnerState, ParsePolicy, PolicyWrapper},
+    KeyValuePair, ParsedLine,
+};
+
+use super::TrivialParsePolicy;
+
+/// The value given to flags by [FlagPolicy].
+pub const FLAG_VALUE: &str = "true";
+
+/// The default predicate for [FlagPolicy]: whether a token matches `[A-Za-z_][A-Za-z0-9_-]*`.
+pub fn is_flag_token(token: &str) -> bool {
+    let mut bytes = token.bytes();
+    matches!(bytes.next(), Some(b) if b.is_ascii_alphabetic() || b == b'_')
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A parse policy wrapping another, that treats a keyless line consisting of a single flag token,
+/// such as `readonly`, as a pair with that key and the value [FLAG_VALUE].
+///
+/// Surrounding whitespace is trimmed from the token before checking it with the predicate,
+/// and lines that are not flags remain keyless.
+/// The value is passed to the inner policy like any other.
+pub struct FlagPolicy<P = TrivialParsePolicy> {
+    inner: P,
+    is_flag: fn(&str) -> bool,
+}
+
+impl<P: ParsePolicy> FlagPolicy<P> {
+    /// Wrap a policy, accepting flag tokens matching [is_flag_token].
+    pub fn new(inner: P) -> Self {
+        Self::with_predicate(inner, is_flag_token)
+    }
+
+    /// Wrap a policy, accepting flag tokens for which `is_flag` returns true.
+    pub fn with_predicate(inner: P, is_flag: fn(&str) -> bool) -> Self {
+        Self { inner, is_flag }
+    }
+}
+
+impl<P: ParsePolicy + Default> Default for FlagPolicy<P> {
+    fn default() -> Self {
+        Self::new(P::default())
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for FlagPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn parse_line(&self, state: &mut InnerState<Self>, line: &str) -> ParsedLine {
+        match self.inner.parse_line(state, line) {
+            ParsedLine::KeylessLine(v) if (self.is_flag)(v.trim()) => {
+                ParsedLine::Pair(KeyValuePair {
+                    key: v.trim().to_string(),
+                    value: FLAG_VALUE.to_string(),
+                })
+            }
+            parsed => parsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_flag_token, FlagPolicy};
+    use crate::{policies::SPDXParsePolicy, KVParser, Output, ParserOutput};
+
+    #[test]
+    fn flags() {
+        assert!(is_flag_token("read-only_2"));
+        assert!(is_flag_token("_x"));
+        assert!(!is_flag_token("2x"));
+        assert!(!is_flag_token("two words"));
+        assert!(!is_flag_token(""));
+
+        let mut parser: KVParser<FlagPolicy> = KVParser::default();
+        assert_eq!(
+            parser.process_line("  readonly ").ok().unwrap(),
+            ("readonly", "true")
+        );
+        assert_eq!(parser.process_line("a: b").ok().unwrap(), ("a", "b"));
+        assert_eq!(
+            parser.process_line("not a flag").into_inner(),
+            Output::KeylessLine("not a flag".to_string())
+        );
+        assert_eq!(parser.process_line("").into_inner(), Output::EmptyLine);
+
+        let mut parser = KVParser::new(FlagPolicy::with_predicate(SPDXParsePolicy {}, |token| {
+            token.starts_with('+')
+        }));
+        assert_eq!(parser.process_line("+x").ok().unwrap(), ("+x", "true"));
+        assert!(parser.process_line("readonly").ok().is_none());
+    }
+}
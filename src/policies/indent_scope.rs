@@ -0,0 +1,157 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    KeyValuePair, ParsedLine,
+};
+
+/// The number of bytes of leading whitespace in a line.
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+#[derive(Debug, Default, Clone)]
+/// A parse policy for indentation-scoped values, as in Python or YAML.
+///
+/// A key with an empty value, written as `key:` or `key: `, starts a multi-line value
+/// made of the following lines that are indented more than the key line.
+/// The value ends before the first non-empty line indented no more than the key line,
+/// which is then processed again with [ProcessedContinuationValue::AbortAndReprocess].
+///
+/// The indentation of the first line of the value is removed from every line of the value.
+/// Empty lines within the value are kept, including any just before the end of the value.
+/// Indentation is removed from keys, and other values are kept as-is.
+/// A value in progress at the end of input is complete, as reported by [ParsePolicy::finish].
+pub struct IndentScopePolicy;
+
+/// The [ParsePolicy::State] of [IndentScopePolicy]: the indentation of the current key line and value.
+#[derive(Debug, Default, Clone)]
+pub struct IndentScopeState {
+    line_indent: usize,
+    scope_indent: usize,
+    value_indent: Option<usize>,
+}
+
+impl IndentScopeState {
+    /// Record the indentation of a line, returning the line without it.
+    pub(super) fn strip_line_indent<'a>(&mut self, line: &'a str) -> &'a str {
+        self.line_indent = indent_of(line);
+        &line[self.line_indent..]
+    }
+
+    /// Start a value scoped to the most recent line passed to [IndentScopeState::strip_line_indent].
+    pub(super) fn start_scope(&mut self) {
+        self.scope_indent = self.line_indent;
+        self.value_indent = None;
+    }
+
+    /// Remove the indentation of the value from a non-empty continuation line,
+    /// or return `None` if the line is not indented more than the key line, ending the value.
+    pub(super) fn dedent<'a>(&mut self, continuation_line: &'a str) -> Option<&'a str> {
+        let indent = indent_of(continuation_line);
+        if indent <= self.scope_indent {
+            return None;
+        }
+        let value_indent = *self.value_indent.get_or_insert(indent);
+        Some(&continuation_line[indent.min(value_indent)..])
+    }
+}
+
+impl ParsePolicy for IndentScopePolicy {
+    type State = IndentScopeState;
+
+    fn parse_line(&self, state: &mut IndentScopeState, line: &str) -> ParsedLine {
+        let content = state.strip_line_indent(line);
+        match content.trim_end().strip_suffix(':') {
+            Some(key) if !key.is_empty() && !key.contains(": ") => ParsedLine::Pair(KeyValuePair {
+                key: key.to_string(),
+                value: String::new(),
+            }),
+            _ => ParsedLine::from(content),
+        }
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut IndentScopeState,
+        _key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        if value.trim().is_empty() {
+            state.start_scope();
+            ProcessedValue::StartOfMultiline(None)
+        } else {
+            ProcessedValue::CompleteValue(value.into())
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut IndentScopeState,
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        if continuation_line.trim().is_empty() {
+            return ProcessedContinuationValue::ContinueMultiline(Some("".into()));
+        }
+        match state.dedent(continuation_line) {
+            Some(line) => ProcessedContinuationValue::ContinueMultiline(Some(line.into())),
+            None => ProcessedContinuationValue::AbortAndReprocess,
+        }
+    }
+
+    fn finish<'a>(&self, _state: &mut IndentScopeState) -> Option<ProcessedContinuationValue<'a>> {
+        Some(ProcessedContinuationValue::FinishMultiline(None))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IndentScopePolicy;
+    use crate::{KVParser, ParserOutput};
+
+    #[test]
+    fn indent_scope() {
+        let mut parser: KVParser<IndentScopePolicy> = KVParser::default();
+        let input = "\
+a: 1
+b:
+    first
+      nested
+
+    last
+c: 3
+outer:
+  inner:
+      x
+  d: 4
+  e:
+  f: 5
+g: 6
+h:
+  trailing";
+        let pairs: Vec<_> = parser.lines(input).filter_map(|o| o.ok()).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("a", "1"),
+                ("b", "first\n  nested\n\nlast"),
+                ("c", "3"),
+                ("outer", "inner:\n    x\nd: 4\ne:\nf: 5"),
+                ("g", "6"),
+                ("h", "trailing"),
+            ]
+        );
+
+        // Nested scopes are handled by parsing the outer value again.
+        let mut parser: KVParser<IndentScopePolicy> = KVParser::default();
+        let inner = &pairs[3].value;
+        let pairs: Vec<_> = parser.lines(inner).filter_map(|o| o.ok()).collect();
+        assert_eq!(
+            pairs,
+            vec![("inner", "x"), ("d", "4"), ("e", ""), ("f", "5")]
+        );
+    }
+}
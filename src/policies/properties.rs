@@ -0,0 +1,166 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use crate::{
+    parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue},
+    KeyValuePair, ParsedLine,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy for Java-style `.properties` files.
+///
+/// Lines are split on the first unescaped `=` or `:`, and the key is trimmed and unescaped.
+/// A single leading space is removed from the value, and escapes (including `\uXXXX`) are decoded.
+/// A value ending in an unescaped `\` continues on the next line, whose leading whitespace is removed,
+/// with no separator between the joined lines.
+/// Lines starting with `#` or `!` are comments, returned as keyless lines.
+pub struct PropertiesPolicy {}
+
+/// Find the byte offset of the first unescaped `=` or `:`.
+fn find_separator(line: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' | ':' => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split off a trailing unescaped `\`, returning the rest and whether it was found.
+fn strip_continuation(value: &str) -> (&str, bool) {
+    if value.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1 {
+        (&value[..value.len() - 1], true)
+    } else {
+        (value, false)
+    }
+}
+
+/// Decode a `\uXXXX` escape from the start of `chars`, consuming it if valid.
+fn decode_unicode_escape(chars: &mut std::str::Chars) -> Option<char> {
+    let hex = chars.as_str().get(..4)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let decoded = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+    chars.nth(3);
+    Some(decoded)
+}
+
+/// Decode escape sequences, only allocating if there are any.
+///
+/// Malformed `\u` escapes (including surrogates) are kept literally.
+fn unescape(s: &str) -> Cow<'_, str> {
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\x0c'),
+            Some('u') => match decode_unicode_escape(&mut chars) {
+                Some(decoded) => out.push(decoded),
+                None => out.push_str("\\u"),
+            },
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    Cow::Owned(out)
+}
+
+impl ParsePolicy for PropertiesPolicy {
+    type State = ();
+
+    fn parse_line(&self, _state: &mut (), line: &str) -> ParsedLine {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() {
+            return ParsedLine::EmptyLine;
+        }
+        if trimmed.starts_with('#') || trimmed.starts_with('!') {
+            return ParsedLine::KeylessLine(line.to_string());
+        }
+        match find_separator(trimmed) {
+            Some(delim) => ParsedLine::Pair(KeyValuePair {
+                key: unescape(trimmed[..delim].trim()).into_owned(),
+                value: trimmed[delim + 1..].to_string(),
+            }),
+            None => ParsedLine::KeylessLine(line.to_string()),
+        }
+    }
+
+    fn process_value<'a>(&self, _state: &mut (), _key: &str, value: &'a str) -> ProcessedValue<'a> {
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match strip_continuation(value) {
+            (value, true) => ProcessedValue::StartOfMultiline(Some(unescape(value))),
+            (value, false) => ProcessedValue::CompleteValue(unescape(value)),
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        _state: &mut (),
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        match strip_continuation(continuation_line.trim_start()) {
+            (value, true) => ProcessedContinuationValue::AppendToPrevious(unescape(value)),
+            (value, false) => ProcessedContinuationValue::FinishAppendToPrevious(unescape(value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PropertiesPolicy;
+    use crate::{KVParser, Output, ParserOutput};
+
+    #[test]
+    fn properties() {
+        let mut parser: KVParser<PropertiesPolicy> = KVParser::default();
+        assert_eq!(parser.process_line("a=b").ok().unwrap(), ("a", "b"));
+        assert_eq!(parser.process_line("  a = b ").ok().unwrap(), ("a", "b "));
+        assert_eq!(parser.process_line("a:  b").ok().unwrap(), ("a", " b"));
+        assert_eq!(
+            parser.process_line("a\\=b\\:c=d").ok().unwrap(),
+            ("a=b:c", "d")
+        );
+        assert_eq!(
+            parser.process_line("url=http://x").ok().unwrap(),
+            ("url", "http://x")
+        );
+        assert_eq!(
+            parser.process_line("a=\\u00e9\\t\\\\\\uzzzz").ok().unwrap(),
+            ("a", "é\t\\\\uzzzz")
+        );
+        assert_eq!(
+            parser.process_line("# comment").into_inner(),
+            Output::KeylessLine("# comment".to_string())
+        );
+        assert_eq!(
+            parser.process_line("no separator").into_inner(),
+            Output::KeylessLine("no separator".to_string())
+        );
+        assert_eq!(parser.process_line("   ").into_inner(), Output::EmptyLine);
+
+        // An escaped backslash at the end does not continue the line
+        assert_eq!(parser.process_line("a=b\\\\").ok().unwrap(), ("a", "b\\"));
+        assert!(parser.process_line("a=b\\").value().is_pending());
+        assert!(parser.process_line("    c\\").value().is_pending());
+        assert_eq!(parser.process_line("  d").ok().unwrap(), ("a", "bcd"));
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy that trims whitespace from values unless they are surrounded by double quotes,
+/// in which case the quotes are removed and the whitespace inside them is kept.
+///
+/// A quoted value without a closing quote on the same line continues across multiple lines,
+/// kept verbatim, until a line ending in the closing quote.
+/// There is no escaping of quotes within a quoted value.
+pub struct SmartTrimPolicy {}
+
+impl ParsePolicy for SmartTrimPolicy {
+    type State = ();
+
+    fn process_value<'a>(&self, _state: &mut (), _key: &str, value: &'a str) -> ProcessedValue<'a> {
+        let value = value.trim_start();
+        match value.strip_prefix('"') {
+            Some(quoted) => match quoted.trim_end().strip_suffix('"') {
+                Some(inner) => ProcessedValue::CompleteValue(inner.into()),
+                None => ProcessedValue::StartOfMultiline(Some(quoted.into())),
+            },
+            None => ProcessedValue::CompleteValue(value.trim_end().into()),
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        _state: &mut (),
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        match continuation_line.trim_end().strip_suffix('"') {
+            Some(line) => ProcessedContinuationValue::FinishMultiline(Some(line.into())),
+            None => ProcessedContinuationValue::ContinueMultiline(Some(continuation_line.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SmartTrimPolicy;
+    use crate::{KVParser, ParserOutput};
+
+    #[test]
+    fn smart_trim() {
+        let mut parser: KVParser<SmartTrimPolicy> = KVParser::default();
+        assert_eq!(
+            parser.process_line("key:  bare  ").ok().unwrap(),
+            ("key", "bare")
+        );
+        assert_eq!(
+            parser.process_line("key:  \"  spaced  \" ").ok().unwrap(),
+            ("key", "  spaced  ")
+        );
+        assert_eq!(parser.process_line("key: \"\"").ok().unwrap(), ("key", ""));
+        assert_eq!(
+            parser.process_line("key: a \"b\"").ok().unwrap(),
+            ("key", "a \"b\"")
+        );
+
+        assert!(parser.process_line("key: \"").value().is_pending());
+        assert!(parser.process_line("  first  ").value().is_pending());
+        assert_eq!(
+            parser.process_line("  second\"").ok().unwrap(),
+            ("key", "\n  first  \n  second")
+        );
+
+        assert!(parser.process_line("key: \" start ").value().is_pending());
+        assert_eq!(
+            parser.process_line("end\"").ok().unwrap(),
+            ("key", " start \nend")
+        );
+    }
+}
@@ -0,0 +1,181 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use super::{
+    map_continuation, needs_text_tags, wrap_in_text_tags, SPDXParsePolicy, ESCAPED_TEXT_CLOSE_TAG,
+    TEXT_CLOSE_TAG, TEXT_OPEN_TAG,
+};
+use crate::parse_policy::{
+    InnerState, ParsePolicy, PolicyWrapper, ProcessedContinuationValue, ProcessedValue,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy layered on [SPDXParsePolicy], with escapes within `<text>` values
+/// so that they may contain a literal `</text>`.
+///
+/// Within a `<text>` value, `<\/text>` is an escaped `</text>`, which does not end the value,
+/// and `\\` is an escaped `\`. Any other backslash is kept as-is.
+/// Values that are not in `<text>` are not unescaped.
+pub struct SPDXEscapedTextPolicy {
+    inner: SPDXParsePolicy,
+}
+
+/// Escape `\` and then `</text>`, only allocating if there are any.
+fn escape_text(value: &str) -> Cow<'_, str> {
+    if value.contains('\\') || value.contains(TEXT_CLOSE_TAG) {
+        Cow::Owned(
+            value
+                .replace('\\', "\\\\")
+                .replace(TEXT_CLOSE_TAG, ESCAPED_TEXT_CLOSE_TAG),
+        )
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Undo [escape_text], only allocating if there are any backslashes.
+fn unescape_text(value: Cow<'_, str>) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return value;
+    }
+    let mut unescaped = String::with_capacity(value.len());
+    let mut rest = &*value;
+    while let Some(i) = rest.find('\\') {
+        let (before, from_backslash) = rest.split_at(i);
+        if before.ends_with('<') && from_backslash.starts_with(&ESCAPED_TEXT_CLOSE_TAG[1..]) {
+            unescaped.push_str(&before[..i - 1]);
+            unescaped.push_str(TEXT_CLOSE_TAG);
+            rest = &from_backslash[ESCAPED_TEXT_CLOSE_TAG.len() - 1..];
+        } else {
+            unescaped.push_str(before);
+            unescaped.push('\\');
+            // Skip the second backslash of an escaped one.
+            let skip = if from_backslash.starts_with("\\\\") {
+                2
+            } else {
+                1
+            };
+            rest = &from_backslash[skip..];
+        }
+    }
+    unescaped.push_str(rest);
+    Cow::Owned(unescaped)
+}
+
+impl PolicyWrapper for SPDXEscapedTextPolicy {
+    type Inner = SPDXParsePolicy;
+
+    fn inner(&self) -> &SPDXParsePolicy {
+        &self.inner
+    }
+
+    /// Wraps values as [SPDXParsePolicy] does, escaping them if they are wrapped.
+    fn wrap_value<'a>(&self, _key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        if needs_text_tags(value) {
+            wrap_in_text_tags(&escape_text(value)).map(|wrapped| Cow::Owned(wrapped.into_owned()))
+        } else {
+            Some(Cow::Borrowed(value))
+        }
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        let in_text = value.trim_start().starts_with(TEXT_OPEN_TAG);
+        match self.inner.process_value(state, key, value) {
+            ProcessedValue::CompleteValue(value) if in_text => {
+                ProcessedValue::CompleteValue(unescape_text(value))
+            }
+            ProcessedValue::StartOfMultiline(value) => {
+                ProcessedValue::StartOfMultiline(value.map(unescape_text))
+            }
+            ProcessedValue::CompleteValueAndReprocessRest(value, rest) => {
+                ProcessedValue::CompleteValueAndReprocessRest(unescape_text(value), rest)
+            }
+            processed => processed,
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        map_continuation(
+            self.inner
+                .process_continuation(state, key, continuation_line),
+            unescape_text,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SPDXEscapedTextPolicy;
+    use crate::{parse_policy::ParsePolicy, policies::SPDXParsePolicy, KVParser, ParserOutput};
+
+    #[test]
+    fn spdx_escaped_text() {
+        let mut parser: KVParser<SPDXEscapedTextPolicy> = KVParser::default();
+        assert_eq!(
+            parser
+                .process_line("key: <text>x<\\/text> \\\\ y</text>")
+                .ok()
+                .unwrap(),
+            ("key", "x</text> \\ y")
+        );
+        assert!(parser
+            .process_line("key: <text>a <\\/text> b")
+            .value()
+            .is_pending());
+        assert!(parser.process_line("<\\/text>\\").value().is_pending());
+        assert_eq!(
+            parser.process_line("c</text>").ok().unwrap(),
+            ("key", "a </text> b\n</text>\\\nc")
+        );
+        // Not unescaped outside of <text>
+        assert_eq!(
+            parser.process_line("key: x<\\/text> \\\\").ok().unwrap(),
+            ("key", "x<\\/text> \\\\")
+        );
+
+        // The default policy has no escapes.
+        let mut parser: KVParser<SPDXParsePolicy> = KVParser::default();
+        assert_eq!(
+            parser
+                .process_line("key: <text>x<\\/text></text>")
+                .ok()
+                .unwrap(),
+            ("key", "x<\\/text>")
+        );
+    }
+
+    #[test]
+    fn spdx_escaped_text_round_trip() {
+        let policy = SPDXEscapedTextPolicy::default();
+        assert_eq!(
+            policy.wrap_value("key", "a </text> b\nc").unwrap(),
+            "<text>a <\\/text> b\nc</text>"
+        );
+        assert_eq!(policy.wrap_value("key", "a \nb"), None);
+        for value in [
+            "a </text> b\nc",
+            "a <\\/text> b\nc",
+            "a \\</text>\n\\\\",
+            "<\\\\/text>\n<",
+            "plain",
+        ] {
+            let mut parser: KVParser<SPDXEscapedTextPolicy> = KVParser::default();
+            let line = format!("key: {}", policy.wrap_value("key", value).unwrap());
+            let pairs: Vec<_> = parser.lines(&line).filter_map(|o| o.ok()).collect();
+            assert_eq!(pairs, vec![("key", value)]);
+        }
+    }
+}
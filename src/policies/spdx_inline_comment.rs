@@ -0,0 +1,141 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use super::{SPDXParsePolicy, TEXT_CLOSE_TAG, TEXT_OPEN_TAG};
+use crate::parse_policy::{InnerState, ParsePolicy, PolicyWrapper, ProcessedValue};
+
+const DEFAULT_MARKER: &str = " ##";
+
+#[derive(Debug, Clone)]
+/// A parse policy layered on [SPDXParsePolicy], that strips a trailing inline comment,
+/// such as `SPDXID: SPDXRef-1 ## generated`, from single-line values.
+///
+/// Values starting with `<text>` are left untouched, as are the continuation lines
+/// of a multi-line value, since the marker may legitimately appear in free text.
+/// The default marker is ` ##`: the space avoids stripping from values like `a##b`.
+/// An empty or whitespace-only marker strips nothing.
+pub struct SPDXInlineCommentPolicy {
+    inner: SPDXParsePolicy,
+    marker: String,
+}
+
+impl SPDXInlineCommentPolicy {
+    /// Create a policy stripping everything from `marker` to the end of a value.
+    pub fn new(marker: impl Into<String>) -> Self {
+        Self {
+            inner: SPDXParsePolicy {},
+            marker: marker.into(),
+        }
+    }
+
+    /// Whether a value would have an inline comment stripped from it.
+    fn has_comment(&self, value: &str) -> bool {
+        // The delimiter may have consumed the leading whitespace of the marker.
+        let marker = self.marker.as_str();
+        !marker.trim().is_empty()
+            && (value.trim_start().starts_with(marker.trim_start()) || value.contains(marker))
+    }
+}
+
+impl Default for SPDXInlineCommentPolicy {
+    fn default() -> Self {
+        Self::new(DEFAULT_MARKER)
+    }
+}
+
+impl PolicyWrapper for SPDXInlineCommentPolicy {
+    type Inner = SPDXParsePolicy;
+
+    fn inner(&self) -> &SPDXParsePolicy {
+        &self.inner
+    }
+
+    /// Wraps values as [SPDXParsePolicy] does, and also wraps values with an inline comment
+    /// so that it is not stripped when the output is parsed again.
+    fn wrap_value<'a>(&self, key: &str, value: &'a str) -> Option<Cow<'a, str>> {
+        match self.inner.wrap_value(key, value) {
+            Some(Cow::Borrowed(value)) if self.has_comment(value) => Some(Cow::Owned(format!(
+                "{}{}{}",
+                TEXT_OPEN_TAG, value, TEXT_CLOSE_TAG
+            ))),
+            wrapped => wrapped,
+        }
+    }
+
+    fn process_value<'a>(
+        &self,
+        state: &mut InnerState<Self>,
+        key: &str,
+        value: &'a str,
+    ) -> ProcessedValue<'a> {
+        if value.trim_start().starts_with(TEXT_OPEN_TAG) || !self.has_comment(value) {
+            return self.inner.process_value(state, key, value);
+        }
+        let value = match value.find(self.marker.as_str()) {
+            Some(i) => value[..i].trim_end(),
+            None => "",
+        };
+        self.inner.process_value(state, key, value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SPDXInlineCommentPolicy;
+    use crate::{parse_policy::ParsePolicy, KVParser, ParserOutput};
+
+    #[test]
+    fn spdx_inline_comment() {
+        let mut parser: KVParser<SPDXInlineCommentPolicy> = KVParser::default();
+        assert_eq!(
+            parser
+                .process_line("SPDXID: SPDXRef-1 ## generated")
+                .ok()
+                .unwrap(),
+            ("SPDXID", "SPDXRef-1")
+        );
+        assert_eq!(parser.process_line("a: b##c").ok().unwrap(), ("a", "b##c"));
+        assert_eq!(parser.process_line("a: ## only").ok().unwrap(), ("a", ""));
+        assert_eq!(
+            parser.process_line("a: <text>b ## c</text>").ok().unwrap(),
+            ("a", "b ## c")
+        );
+        assert!(parser.process_line("a: <text>b ## c").value().is_pending());
+        assert_eq!(
+            parser.process_line("d ## e</text>").ok().unwrap(),
+            ("a", "b ## c\nd ## e")
+        );
+
+        let mut parser = KVParser::new(SPDXInlineCommentPolicy::new(" //"));
+        assert_eq!(
+            parser.process_line("a: b ## c // d").ok().unwrap(),
+            ("a", "b ## c")
+        );
+
+        let policy = SPDXInlineCommentPolicy::default();
+        assert_eq!(
+            policy.wrap_value("a", "b ## c").unwrap(),
+            "<text>b ## c</text>"
+        );
+        assert_eq!(policy.wrap_value("a", "b").unwrap(), "b");
+        assert_eq!(policy.wrap_value("a", "## x").unwrap(), "<text>## x</text>");
+        let mut parser = KVParser::new(policy);
+        assert_eq!(
+            parser.process_line("a: <text>## x</text>").ok().unwrap(),
+            ("a", "## x")
+        );
+
+        for marker in ["", "  "] {
+            let policy = SPDXInlineCommentPolicy::new(marker);
+            assert_eq!(policy.wrap_value("a", "b").unwrap(), "b");
+            let mut parser = KVParser::new(policy);
+            assert_eq!(
+                parser.process_line("a: b ## c").ok().unwrap(),
+                ("a", "b ## c")
+            );
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use crate::{
+    parse_policy::{InnerState, ParsePolicy, PolicyWrapper},
+    KeyValuePair, ParsedLine,
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy wrapping another, that treats a keyless line ending in `:`,
+/// such as `key:`, as a key with an empty value.
+///
+/// The line must end exactly in `:`, with no trailing whitespace,
+/// and the key must not be empty.
+/// The empty value is passed to the inner policy like any other.
+pub struct TrailingColonPolicy<P> {
+    inner: P,
+}
+
+impl<P: ParsePolicy> TrailingColonPolicy<P> {
+    /// Wrap a policy, accepting keys with a trailing colon and no value.
+    pub fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: ParsePolicy> PolicyWrapper for TrailingColonPolicy<P> {
+    type Inner = P;
+
+    fn inner(&self) -> &P {
+        &self.inner
+    }
+
+    fn parse_line(&self, state: &mut InnerState<Self>, line: &str) -> ParsedLine {
+        match self.inner.parse_line(state, line) {
+            ParsedLine::KeylessLine(v) => match v.strip_suffix(':') {
+                Some(key) if !key.trim().is_empty() => ParsedLine::Pair(KeyValuePair {
+                    key: key.to_string(),
+                    value: String::new(),
+                }),
+                _ => ParsedLine::KeylessLine(v),
+            },
+            parsed => parsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TrailingColonPolicy;
+    use crate::{policies::SPDXParsePolicy, KVParser, Output, ParserOutput};
+
+    #[test]
+    fn trailing_colon() {
+        let mut parser = KVParser::new(TrailingColonPolicy::new(SPDXParsePolicy {}));
+        assert_eq!(parser.process_line("key:").ok().unwrap(), ("key", ""));
+        assert_eq!(parser.process_line("key: ").ok().unwrap(), ("key", ""));
+        assert_eq!(
+            parser.process_line("key: value").ok().unwrap(),
+            ("key", "value")
+        );
+        assert_eq!(
+            parser.process_line("key:value").into_inner(),
+            Output::KeylessLine("key:value".to_string())
+        );
+        assert_eq!(
+            parser.process_line("key: \t:").ok().unwrap(),
+            ("key", "\t:")
+        );
+        assert_eq!(
+            parser.process_line(":").into_inner(),
+            Output::KeylessLine(":".to_string())
+        );
+        assert_eq!(
+            parser.process_line("key: <text>a").into_inner(),
+            Output::Pending
+        );
+        // Continuation lines are not affected.
+        assert!(parser.process_line("b:").value().is_pending());
+        assert_eq!(
+            parser.process_line("</text>").ok().unwrap(),
+            ("key", "a\nb:\n")
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use std::borrow::Cow;
+
+use crate::parse_policy::{ParsePolicy, ProcessedContinuationValue, ProcessedValue};
+
+#[derive(Debug, Default, Clone, Copy)]
+/// A parse policy for list-style values, where a value ending in `,` continues on the next line,
+/// until a line that does not end in `,`.
+///
+/// Values and continuation lines are trimmed.
+/// If commas are kept, the lines are joined with no separator, so `a,` then `b` becomes `a,b`.
+/// Otherwise the trailing commas are removed and each item is a line of the value,
+/// so `a,` then `b` becomes `a\nb`.
+/// The default drops the commas.
+pub struct TrailingCommaPolicy {
+    keep_comma: bool,
+}
+
+impl TrailingCommaPolicy {
+    /// Create a policy, keeping the trailing commas in joined values if `keep_comma` is true.
+    pub fn new(keep_comma: bool) -> Self {
+        Self { keep_comma }
+    }
+
+    /// Returns the line without its trailing comma (unless kept) and whether it had one.
+    fn strip_comma<'a>(&self, line: &'a str) -> (&'a str, bool) {
+        let line = line.trim();
+        match line.strip_suffix(',') {
+            Some(_) if self.keep_comma => (line, true),
+            Some(stripped) => (stripped.trim_end(), true),
+            None => (line, false),
+        }
+    }
+}
+
+impl ParsePolicy for TrailingCommaPolicy {
+    type State = ();
+
+    fn process_value<'a>(&self, _state: &mut (), _key: &str, value: &'a str) -> ProcessedValue<'a> {
+        match self.strip_comma(value) {
+            (value, true) => ProcessedValue::StartOfMultiline(Some(value.into())),
+            (value, false) => ProcessedValue::CompleteValue(value.into()),
+        }
+    }
+
+    fn process_continuation<'a>(
+        &self,
+        _state: &mut (),
+        _key: &str,
+        continuation_line: &'a str,
+    ) -> ProcessedContinuationValue<'a> {
+        let (line, continues) = self.strip_comma(continuation_line);
+        let line = Cow::Borrowed(line);
+        match (continues, self.keep_comma) {
+            (true, true) => ProcessedContinuationValue::AppendToPrevious(line),
+            (false, true) => ProcessedContinuationValue::FinishAppendToPrevious(line),
+            (true, false) => ProcessedContinuationValue::ContinueMultiline(Some(line)),
+            (false, false) => ProcessedContinuationValue::FinishMultiline(Some(line)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TrailingCommaPolicy;
+    use crate::{KVParser, ParserOutput};
+
+    #[test]
+    fn trailing_comma() {
+        let mut parser: KVParser<TrailingCommaPolicy> = KVParser::default();
+        assert_eq!(parser.process_line("key: a").ok().unwrap(), ("key", "a"));
+        assert!(parser.process_line("key: a ,").value().is_pending());
+        assert!(parser.process_line("  b,").value().is_pending());
+        assert_eq!(
+            parser.process_line("  c ").ok().unwrap(),
+            ("key", "a\nb\nc")
+        );
+
+        let mut parser = KVParser::new(TrailingCommaPolicy::new(true));
+        assert!(parser.process_line("key: a, ").value().is_pending());
+        assert!(parser.process_line("  b,").value().is_pending());
+        assert_eq!(parser.process_line("  c").ok().unwrap(), ("key", "a,b,c"));
+        assert_eq!(
+            parser.process_line("key: a,b").ok().unwrap(),
+            ("key", "a,b")
+        );
+    }
+}
@@ -2,9 +2,14 @@
 //
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
+use std::collections::{HashMap, HashSet, VecDeque};
+
 use crate::{
-    parse_policy::ParsePolicy, record_emitter::RecordEmitter, KVParser, KeyValuePair, LineNumber,
-    Output,
+    emitters::BlankLineRecordEmitter,
+    parse_policy::ParsePolicy,
+    policies::{KeyValueOp, ValueOp},
+    record_emitter::RecordEmitter,
+    KVParser, KeyValuePair, LineNumber, Output, ParseError, ParserOutput,
 };
 
 /// An error from operations on a Record
@@ -19,6 +24,9 @@ pub enum RecordError {
     #[error("Missing mandatory field {0}")]
     MissingField(String),
 
+    #[error("Unexpected field {0}")]
+    UnexpectedKey(String),
+
     #[error("Out of data")]
     OutOfData,
 
@@ -26,34 +34,307 @@ pub enum RecordError {
     Message(String),
 }
 
+/// How [Record::merge] handles fields of the other record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Keep all fields of both records, appending the other record's fields.
+    Append,
+    /// Replace fields with the same key as fields in the other record.
+    ///
+    /// Each replaced field keeps its original position:
+    /// if the records have different numbers of fields with a key,
+    /// extra fields in this record are removed and extra fields from the other record are appended.
+    Override,
+    /// Keep fields of this record, appending only fields of the other record whose keys are not present.
+    KeepFirst,
+}
+
+/// What [Record::merge_with_report] changed, for example for an audit log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MergeReport {
+    /// `(key, old_value, new_value)` for each field replaced by a field of the other record.
+    pub overridden: Vec<(String, String, String)>,
+    /// Fields of the other record appended, since this record had no field left to replace.
+    pub added: Vec<KeyValuePair>,
+    /// Fields of this record removed, since the other record had fewer fields with that key.
+    pub removed: Vec<KeyValuePair>,
+    /// Keys of this record not present in the other record, in order of first appearance.
+    pub untouched: Vec<String>,
+}
+
+/// A function setting a field of `T` from a value, used with [Record::read_into].
+pub type FieldSetter<T> = fn(&mut T, &str);
+
+/// Implemented by types that can be extracted from the fields of a [Record].
+///
+/// With the `derive` feature enabled, this can be derived with `#[derive(FromRecord)]`
+/// for structs whose fields implement `FromStr`.
+/// Fields of type `Option<T>` are optional, and the key for a field may be set with
+/// `#[kv(key = "SomeKey")]` (the default is the field name).
+pub trait FromRecord: Sized {
+    /// Extract an instance from a record.
+    fn from_record(record: &Record) -> Result<Self, RecordError>;
+}
+
+#[cfg(feature = "derive")]
+pub use key_value_parser_derive::FromRecord;
+
+/// Parse the index from a key of the form `base[N]` or `base.N`.
+fn index_for_key(key: &str, base_key: &str) -> Option<usize> {
+    let suffix = key.strip_prefix(base_key)?;
+    let index = match suffix.strip_prefix('[') {
+        Some(bracketed) => bracketed.strip_suffix(']')?,
+        None => suffix.strip_prefix('.')?,
+    };
+    if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    index.parse().ok()
+}
+
+/// Replace `${KEY}` references in a value, skipping keys in `expanding` to break cycles.
+fn expand_references<'a>(
+    value: &str,
+    values: &HashMap<&'a str, &'a str>,
+    expanding: &mut Vec<&'a str>,
+) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let reference = &rest[start..];
+        let end = match reference.find('}') {
+            Some(end) => end,
+            None => {
+                rest = reference;
+                break;
+            }
+        };
+        match values.get_key_value(&reference[2..end]) {
+            Some((&key, &referenced)) if !expanding.contains(&key) => {
+                expanding.push(key);
+                out.push_str(&expand_references(referenced, values, expanding));
+                expanding.pop();
+            }
+            _ => out.push_str(&reference[..=end]),
+        }
+        rest = &reference[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
 /// An ordered collection of key-value pairs, providing some helper functions above and beyond what vector provides.
-pub struct Record(Vec<KeyValuePair>);
+///
+/// Equality with `==` is order-sensitive: see [Record::eq_unordered] for an alternative.
+/// Line numbers are not compared.
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    fields: Vec<KeyValuePair>,
+    record_type: Option<String>,
+    /// Empty, or the line number of each field, if known.
+    line_numbers: Vec<Option<usize>>,
+}
 
-impl Default for Record {
-    fn default() -> Self {
-        Self(Vec::default())
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.fields == other.fields && self.record_type == other.record_type
     }
 }
 
 impl Record {
     /// Create from a vector.
     pub fn new(fields: Vec<KeyValuePair>) -> Self {
-        Self(fields)
+        Self {
+            fields,
+            record_type: None,
+            line_numbers: vec![],
+        }
+    }
+
+    /// Set the line number of each field, as from [RecordParser::with_line_numbers].
+    fn with_line_numbers(mut self, line_numbers: Vec<Option<usize>>) -> Self {
+        debug_assert_eq!(line_numbers.len(), self.fields.len());
+        self.line_numbers = line_numbers;
+        self
+    }
+
+    /// Iterate over the fields along with their line numbers, if known.
+    ///
+    /// Line numbers are only known for records from a [RecordParser] with
+    /// [RecordParser::with_line_numbers] enabled.
+    pub fn iter_with_line_numbers(
+        &self,
+    ) -> impl Iterator<Item = (Option<usize>, &KeyValuePair)> + '_ {
+        self.fields
+            .iter()
+            .enumerate()
+            .map(move |(i, pair)| (self.line_numbers.get(i).copied().flatten(), pair))
+    }
+
+    /// Get the line number of the first field with the given key, if known.
+    ///
+    /// See [Record::iter_with_line_numbers].
+    pub fn line_for_key(&self, key: &str) -> Option<usize> {
+        self.iter_with_line_numbers()
+            .find(|(_, pair)| pair.key == key)
+            .and_then(|(line, _)| line)
+    }
+
+    /// Set the type of the record, such as from a leading type line.
+    ///
+    /// See [RecordParser::with_type_line].
+    pub fn with_record_type(mut self, record_type: Option<String>) -> Self {
+        self.record_type = record_type;
+        self
+    }
+
+    /// Get the type of the record, if any.
+    pub fn record_type(&self) -> Option<&str> {
+        self.record_type.as_deref()
     }
 
     /// Extract the inner vector of pairs
     pub fn into_inner(self) -> Vec<KeyValuePair> {
-        self.0
+        self.fields
+    }
+
+    /// Extract the fields, to move them into other structures without cloning:
+    /// the same as [Record::into_inner].
+    pub fn into_pairs(self) -> Vec<KeyValuePair> {
+        self.fields
+    }
+
+    /// Get a shared borrow of the fields as a slice: the same as [Record::as_slice].
+    pub fn pairs(&self) -> &[KeyValuePair] {
+        &self.fields
     }
 
     /// Get a shared borrow of the contained vector.
     pub fn get(&self) -> &Vec<KeyValuePair> {
-        &self.0
+        &self.fields
+    }
+
+    /// Get a shared borrow of the fields as a slice.
+    pub fn as_slice(&self) -> &[KeyValuePair] {
+        &self.fields
+    }
+
+    /// Get a shared borrow of the fields as a slice: the same as [Record::as_slice].
+    pub fn as_pairs(&self) -> &[KeyValuePair] {
+        &self.fields
+    }
+
+    /// Clone the fields into owned `(key, value)` tuples, for example for a flat export.
+    pub fn to_pairs_vec(&self) -> Vec<(String, String)> {
+        self.fields
+            .iter()
+            .map(|pair| (pair.key.clone(), pair.value.clone()))
+            .collect()
+    }
+
+    /// Returns true if the record has no fields.
+    ///
+    /// [RecordParser] does not produce empty records from runs of empty lines,
+    /// but may produce an empty record with only a type: see [RecordParser::with_type_line].
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Get the first field, if any.
+    pub fn first(&self) -> Option<&KeyValuePair> {
+        self.fields.first()
+    }
+
+    /// Get the last field, if any.
+    pub fn last(&self) -> Option<&KeyValuePair> {
+        self.fields.last()
+    }
+
+    /// Get the field at a position, if any.
+    ///
+    /// Named `field` since [Record::get] borrows the whole vector.
+    pub fn field(&self, index: usize) -> Option<&KeyValuePair> {
+        self.fields.get(index)
+    }
+
+    /// Merge the fields of another record into this one, for example to layer settings over defaults.
+    ///
+    /// Fields added from the other record keep their relative order and are placed at the end.
+    /// Line numbers are discarded, since they may refer to different sources.
+    pub fn merge(&mut self, other: Record, strategy: MergeStrategy) {
+        self.line_numbers.clear();
+        match strategy {
+            MergeStrategy::Append => self.fields.extend(other.fields),
+            MergeStrategy::Override => {
+                self.merge_with_report(other);
+            }
+            MergeStrategy::KeepFirst => {
+                let keys: HashSet<String> =
+                    self.fields.iter().map(|pair| pair.key.clone()).collect();
+                self.fields.extend(
+                    other
+                        .fields
+                        .into_iter()
+                        .filter(|pair| !keys.contains(&pair.key)),
+                );
+            }
+        }
+    }
+
+    /// Merge the fields of another record into this one with [MergeStrategy::Override],
+    /// reporting what was overridden, added, removed and left untouched.
+    pub fn merge_with_report(&mut self, other: Record) -> MergeReport {
+        self.line_numbers.clear();
+        let mut report = MergeReport::default();
+        let other_keys: HashSet<String> =
+            other.fields.iter().map(|pair| pair.key.clone()).collect();
+        let mut incoming: Vec<Option<KeyValuePair>> = other.fields.into_iter().map(Some).collect();
+        let fields = core::mem::take(&mut self.fields);
+        for field in fields {
+            if !other_keys.contains(&field.key) {
+                if !report.untouched.contains(&field.key) {
+                    report.untouched.push(field.key.clone());
+                }
+                self.fields.push(field);
+            } else if let Some(replacement) = incoming
+                .iter_mut()
+                .find(|pair| matches!(pair, Some(pair) if pair.key == field.key))
+                .and_then(Option::take)
+            {
+                report
+                    .overridden
+                    .push((field.key, field.value, replacement.value.clone()));
+                self.fields.push(replacement);
+            } else {
+                report.removed.push(field);
+            }
+        }
+        report.added = incoming.into_iter().flatten().collect();
+        self.fields.extend(report.added.iter().cloned());
+        report
+    }
+
+    /// Compare two records as multisets of key-value pairs, ignoring the order of the fields.
+    /// The record types must be equal.
+    pub fn eq_unordered(&self, other: &Record) -> bool {
+        fn sorted_pairs(record: &Record) -> Vec<(&str, &str)> {
+            let mut pairs: Vec<_> = record
+                .fields
+                .iter()
+                .map(|pair| (pair.key.as_str(), pair.value.as_str()))
+                .collect();
+            pairs.sort_unstable();
+            pairs
+        }
+        self.record_type == other.record_type
+            && self.fields.len() == other.fields.len()
+            && sorted_pairs(self) == sorted_pairs(other)
     }
 
     /// Return the number of fields whose key matches the provided key
     pub fn count_fields_with_key(&self, key: &str) -> usize {
-        self.0.iter().filter(|pair| pair.key == key).count()
+        self.fields.iter().filter(|pair| pair.key == key).count()
     }
 
     /// Return an iterator of all field values (in original order) whose key matches the provided key
@@ -61,7 +342,7 @@ impl Record {
         &'a self,
         key: &'a str,
     ) -> Box<dyn Iterator<Item = &'a String> + 'a> {
-        Box::new(self.0.iter().filter_map(move |pair| {
+        Box::new(self.fields.iter().filter_map(move |pair| {
             if pair.key == key {
                 Some(&pair.value)
             } else {
@@ -75,6 +356,190 @@ impl Record {
         self.iter_values_for_key(key).collect()
     }
 
+    /// Populate a value by calling a setter with the value of each required key.
+    ///
+    /// A lighter-weight alternative to [FromRecord]: each key must appear exactly once,
+    /// as in [Record::value_for_required_key].
+    pub fn read_into<T>(
+        &self,
+        init: T,
+        setters: &[(&str, FieldSetter<T>)],
+    ) -> Result<T, RecordError> {
+        let mut result = init;
+        for (key, setter) in setters {
+            setter(&mut result, self.value_for_required_key(key)?);
+        }
+        Ok(result)
+    }
+
+    /// Group the field values by key, with keys in order of first appearance
+    /// and the values for each key in their original order.
+    pub fn group_by_key(&self) -> Vec<(&str, Vec<&str>)> {
+        let mut groups: Vec<(&str, Vec<&str>)> = vec![];
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        for pair in &self.fields {
+            let index = *indices.entry(&pair.key).or_insert_with(|| {
+                groups.push((&pair.key, vec![]));
+                groups.len() - 1
+            });
+            groups[index].1.push(&pair.value);
+        }
+        groups
+    }
+
+    /// Convert into a map from each key to all of its values, like [Record::group_by_key]
+    /// but with map lookup: keys are in order of first appearance
+    /// and the values for each key in their original order.
+    #[cfg(feature = "indexmap")]
+    pub fn into_index_map(self) -> indexmap::IndexMap<String, Vec<String>> {
+        let mut map = indexmap::IndexMap::new();
+        for KeyValuePair { key, value } in self.into_pairs() {
+            map.entry(key).or_insert_with(Vec::new).push(value);
+        }
+        map
+    }
+
+    /// Iterate over the distinct keys, each once, in order of first appearance.
+    ///
+    /// This does not allocate: each field is compared with those before it,
+    /// which is quadratic in the number of fields, but fast for typical small records.
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(move |(i, pair)| !self.fields[..*i].iter().any(|prev| prev.key == pair.key))
+            .map(|(_, pair)| pair.key.as_str())
+    }
+
+    /// The number of distinct keys, as yielded by [Record::keys].
+    pub fn distinct_key_count(&self) -> usize {
+        self.keys().count()
+    }
+
+    /// Gather the values of keys of the form `base_key[N]` or `base_key.N`, sorted by `N`.
+    ///
+    /// Keys with a malformed index, such as `base_key[x]`, are skipped.
+    /// Values with the same index keep their original order.
+    pub fn collect_indexed(&self, base_key: &str) -> Vec<&str> {
+        let mut indexed: Vec<(usize, &str)> = self
+            .fields
+            .iter()
+            .filter_map(|pair| {
+                index_for_key(&pair.key, base_key).map(|index| (index, pair.value.as_str()))
+            })
+            .collect();
+        indexed.sort_by_key(|&(index, _)| index);
+        indexed.into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Apply operations, such as from [AppendAwarePolicy](crate::policies::AppendAwarePolicy),
+    /// in order.
+    ///
+    /// [ValueOp::Set] replaces the first field with the key and removes any others,
+    /// or appends a field if there are none.
+    /// [ValueOp::Append] appends a field, keeping any existing fields with the key:
+    /// see [Record::values_for_key].
+    /// Line numbers are discarded.
+    pub fn apply_ops<I: IntoIterator<Item = KeyValueOp>>(&mut self, ops: I) {
+        self.line_numbers.clear();
+        for KeyValueOp { key, value, op } in ops {
+            match (op, self.fields.iter().position(|pair| pair.key == key)) {
+                (ValueOp::Set, Some(first)) => {
+                    self.fields[first].value = value;
+                    let mut index = 0;
+                    self.fields.retain(|pair| {
+                        index += 1;
+                        index - 1 == first || pair.key != key
+                    });
+                }
+                _ => self.fields.push(KeyValuePair { key, value }),
+            }
+        }
+    }
+
+    /// Rename every field with the key `from` to `to`, keeping its position and value,
+    /// for example to migrate a document to a new schema version.
+    ///
+    /// Returns the number of fields renamed.
+    pub fn rename_key(&mut self, from: &str, to: &str) -> usize {
+        let mut count = 0;
+        for pair in self.fields.iter_mut().filter(|pair| pair.key == from) {
+            pair.key = to.to_string();
+            count += 1;
+        }
+        count
+    }
+
+    /// Replace each `${KEY}` in the field values with the value of the field `KEY`,
+    /// for example in `.env`-style files.
+    ///
+    /// If there are several fields with a key, the last is used.
+    /// References are expanded recursively, but references to unknown keys,
+    /// or that would form a cycle, are left as-is.
+    pub fn expand_variables(&mut self) {
+        let values: HashMap<&str, &str> = self
+            .fields
+            .iter()
+            .map(|pair| (pair.key.as_str(), pair.value.as_str()))
+            .collect();
+        let expanded: Vec<Option<String>> = self
+            .fields
+            .iter()
+            .map(|pair| {
+                if pair.value.contains("${") {
+                    let mut expanding = vec![pair.key.as_str()];
+                    Some(expand_references(&pair.value, &values, &mut expanding))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for (pair, value) in self.fields.iter_mut().zip(expanded) {
+            if let Some(value) = value {
+                pair.value = value;
+            }
+        }
+    }
+
+    /// Convert to a JSON object, where keys with a single value map to a string,
+    /// and keys with multiple values map to an array of strings in their original order.
+    ///
+    /// This loses the order of the fields, and is ambiguous for keys that may be repeated:
+    /// a key that is semantically a list but has exactly one value in this record maps to a string,
+    /// so consumers should accept either form for such keys.
+    /// The record type, if any, is not included.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value;
+        let object = self
+            .group_by_key()
+            .into_iter()
+            .map(|(key, values)| {
+                let value = match values.as_slice() {
+                    [value] => Value::String(value.to_string()),
+                    values => Value::Array(
+                        values
+                            .iter()
+                            .map(|value| Value::String(value.to_string()))
+                            .collect(),
+                    ),
+                };
+                (key.to_string(), value)
+            })
+            .collect();
+        Value::Object(object)
+    }
+
+    /// List each key that appears in more than one field, with the number of such fields,
+    /// in order of first appearance.
+    pub fn find_duplicate_keys(&self) -> Vec<(&str, usize)> {
+        self.group_by_key()
+            .into_iter()
+            .filter(|(_, values)| values.len() > 1)
+            .map(|(key, values)| (key, values.len()))
+            .collect()
+    }
+
     /// Returns the value of a field with the given key, if any, and returns an error if more than one such field exists.
     pub fn value_for_key<'a>(&'a self, key: &'a str) -> Result<Option<&'a String>, RecordError> {
         let mut values = self.iter_values_for_key(key);
@@ -88,6 +553,38 @@ impl Record {
             ))
         }
     }
+    /// Returns the value of a field with the given key parsed with `FromStr`, if any,
+    /// and returns an error if more than one such field exists or the value does not parse.
+    pub fn get_parsed<T: core::str::FromStr>(&self, key: &str) -> Result<Option<T>, RecordError>
+    where
+        T::Err: core::fmt::Display,
+    {
+        self.value_for_key(key)?
+            .map(|value| {
+                value.parse().map_err(|e| {
+                    RecordError::Message(format!("Could not parse value of field {}: {}", key, e))
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the value of a field with the given key as a boolean, if any,
+    /// and returns an error if more than one such field exists or the value is not recognized.
+    ///
+    /// `true`, `yes` and `1` are true, and `false`, `no` and `0` are false, ignoring ASCII case.
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, RecordError> {
+        self.value_for_key(key)?
+            .map(|value| match value.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(true),
+                "false" | "no" | "0" => Ok(false),
+                _ => Err(RecordError::Message(format!(
+                    "Could not parse value of field {}: {:?} is not a boolean",
+                    key, value
+                ))),
+            })
+            .transpose()
+    }
+
     /// Returns the value of a field with the given key, and returns an error if more than one such field exists, or if none exist.
     pub fn value_for_required_key<'a>(&'a self, key: &'a str) -> Result<&'a String, RecordError> {
         let mut values = self.iter_values_for_key(key);
@@ -105,6 +602,23 @@ impl Record {
             None => Err(RecordError::MissingField(key.to_string())),
         }
     }
+
+    /// Checks that every field has a key in `allowed`, for formats with a closed set of keys.
+    ///
+    /// Returns a [RecordError::UnexpectedKey] for each field whose key is not allowed, in order.
+    pub fn validate_keys_in_set(&self, allowed: &HashSet<&str>) -> Result<(), Vec<RecordError>> {
+        let errors: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|field| !allowed.contains(field.key.as_str()))
+            .map(|field| RecordError::UnexpectedKey(field.key.clone()))
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
 }
 
 impl From<Output<Vec<KeyValuePair>>> for Output<Record> {
@@ -119,6 +633,18 @@ impl From<Vec<KeyValuePair>> for Record {
     }
 }
 
+impl core::iter::FromIterator<KeyValuePair> for Record {
+    fn from_iter<I: IntoIterator<Item = KeyValuePair>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl AsRef<[KeyValuePair]> for Record {
+    fn as_ref(&self) -> &[KeyValuePair] {
+        self.as_slice()
+    }
+}
+
 impl From<Record> for Vec<KeyValuePair> {
     fn from(record: Record) -> Self {
         record.into_inner()
@@ -130,6 +656,12 @@ impl From<Record> for Vec<KeyValuePair> {
 pub struct RecordParser<R, P: ParsePolicy> {
     record_emitter: R,
     inner: KVParser<P>,
+    keyless_lines: Vec<LineNumber<String>>,
+    type_line: bool,
+    pending_type: Option<String>,
+    line_numbers: bool,
+    field_lines: Vec<LineNumber<KeyValuePair>>,
+    deferred: VecDeque<LineNumber<Output<Record>>>,
 }
 
 impl<R: RecordEmitter, P: ParsePolicy> RecordParser<R, P> {
@@ -138,6 +670,12 @@ impl<R: RecordEmitter, P: ParsePolicy> RecordParser<R, P> {
         Self {
             record_emitter,
             inner,
+            keyless_lines: vec![],
+            type_line: false,
+            pending_type: None,
+            line_numbers: false,
+            field_lines: vec![],
+            deferred: VecDeque::new(),
         }
     }
 
@@ -145,27 +683,929 @@ impl<R: RecordEmitter, P: ParsePolicy> RecordParser<R, P> {
     ///
     /// If a record has finished is now available, it will
     /// be found in the return value.
+    ///
+    /// If the line has more than one output, as described for [KVParser::take_deferred_output],
+    /// they are all passed to the record emitter.
+    /// If more than one completes a record, the others are held for [RecordParser::take_deferred_output].
     pub fn process_line(&mut self, line: &str) -> LineNumber<Output<Record>> {
-        self.inner
-            .process_line(line)
-            .map(|v| self.record_emitter.accumulate_output(v))
-            .map(|output| output.into())
+        self.deferred.clear();
+        let output = self.inner.process_line(line);
+        let mut output = self.accumulate(output);
+        while let Some(deferred) = self.inner.take_deferred_output() {
+            let deferred = self.accumulate(deferred);
+            match (output.value(), deferred.value()) {
+                (Output::Output(_), Output::Output(_)) => self.deferred.push_back(deferred),
+                (Output::Output(_), _) => {}
+                _ => output = deferred,
+            }
+        }
+        output
+    }
+
+    /// Take the next of any other records completed by the most recent line, in order.
+    ///
+    /// This only happens when more than one output of the line completes a record.
+    /// Call this until it returns `None` after each call to [RecordParser::process_line]:
+    /// any records left are discarded by the next call.
+    pub fn take_deferred_output(&mut self) -> Option<LineNumber<Output<Record>>> {
+        self.deferred.pop_front()
+    }
+
+    /// Pass the output of the inner parser to the record emitter.
+    fn accumulate(
+        &mut self,
+        output: LineNumber<Output<KeyValuePair>>,
+    ) -> LineNumber<Output<Record>> {
+        let output = match output.value() {
+            Output::KeylessLine(v) if self.type_line && self.awaiting_type() => {
+                self.pending_type = Some(v.clone());
+                output.map(|_| Output::Pending)
+            }
+            Output::KeylessLine(v) => {
+                self.keyless_lines
+                    .push(LineNumber::new(output.line_number(), v.clone()));
+                output
+            }
+            _ => output,
+        };
+        if let (true, Output::Output(pair)) = (self.line_numbers, output.value()) {
+            self.field_lines
+                .push(LineNumber::new(output.line_number(), pair.clone()));
+        }
+        output.map(|v| {
+            let emitted = self.record_emitter.accumulate_output(v);
+            self.make_record(emitted)
+        })
+    }
+
+    /// Record the line number of each field, available from [Record::iter_with_line_numbers]
+    /// and [Record::line_for_key].
+    ///
+    /// As elsewhere, the line number of a multi-line field is that of its last line,
+    /// or of its first line if it is ended by [RecordParser::finish], as with [KVParser::finish].
+    /// If the record emitter adds fields of its own, their line numbers are unknown.
+    pub fn with_line_numbers(mut self) -> Self {
+        self.line_numbers = true;
+        self
+    }
+
+    /// Match the fields of an emitted record with the fields passed to the emitter,
+    /// to find their line numbers.
+    fn take_line_numbers(&mut self, fields: &[KeyValuePair]) -> Vec<Option<usize>> {
+        let candidates = core::mem::take(&mut self.field_lines);
+        let mut next = 0;
+        fields
+            .iter()
+            .map(|field| {
+                let found = candidates[next..]
+                    .iter()
+                    .position(|candidate| candidate.value() == field)?;
+                next += found + 1;
+                Some(candidates[next - 1].line_number())
+            })
+            .collect()
+    }
+
+    /// Treat the first keyless line of each record, before any fields, as the type of the record,
+    /// rather than as a keyless line.
+    ///
+    /// The type is available from [Record::record_type].
+    /// A type line followed by the end of the record with no fields produces a record with no fields.
+    pub fn with_type_line(mut self, type_line: bool) -> Self {
+        self.type_line = type_line;
+        self
+    }
+
+    fn awaiting_type(&self) -> bool {
+        self.pending_type.is_none() && self.record_emitter.field_count() == 0
+    }
+
+    fn make_record(&mut self, emitted: Output<Vec<KeyValuePair>>) -> Output<Record> {
+        if !matches!(emitted, Output::Output(_)) && self.record_emitter.field_count() == 0 {
+            // Any record was consumed by the emitter.
+            self.field_lines.clear();
+        }
+        match emitted {
+            Output::Output(fields) if self.line_numbers => {
+                let line_numbers = self.take_line_numbers(&fields);
+                Output::Output(
+                    Record::new(fields)
+                        .with_record_type(self.pending_type.take())
+                        .with_line_numbers(line_numbers),
+                )
+            }
+            Output::Output(fields) => {
+                Output::Output(Record::new(fields).with_record_type(self.pending_type.take()))
+            }
+            Output::EmptyLine if self.pending_type.is_some() => {
+                Output::Output(Record::default().with_record_type(self.pending_type.take()))
+            }
+            other => other.into(),
+        }
     }
 
     /// End the input and return any record in progress
     pub fn end_input(&mut self) -> Output<Record> {
-        self.record_emitter.end_input().into()
+        let emitted = self.record_emitter.end_input();
+        self.make_record(emitted)
+    }
+
+    /// End the input, including any pending multi-line field, and return any record in progress.
+    ///
+    /// This is the way to handle the end of input, including for input
+    /// that does not end in an empty line.
+    /// A multi-line field that was never terminated, as reported by [KVParser::finish],
+    /// is included in the record with the lines of its value found so far.
+    /// Returns `None` if there is no record in progress.
+    pub fn finish(&mut self) -> Option<Record> {
+        let mut outputs = vec![];
+        self.inner.finish_each(|output| outputs.push(output));
+        for output in outputs {
+            let output = output.map(|output| match output {
+                Output::Error(ParseError::UnterminatedMultiline { key, value }) => {
+                    Output::Output(KeyValuePair { key, value })
+                }
+                output => output,
+            });
+            self.accumulate(output);
+        }
+        self.end_input().ok()
+    }
+
+    /// Parse records from the start of a string, stopping after `max_records` records.
+    ///
+    /// Returns the records and the byte offset in `input` where parsing stopped,
+    /// which is the length of `input` if it was all consumed.
+    /// If the end of input is reached, any pending field and record in progress are included.
+    pub fn parse_str_limited(mut self, input: &str, max_records: usize) -> (Vec<Record>, usize) {
+        let mut records = vec![];
+        let mut offset = 0;
+        for line in input.split_inclusive('\n') {
+            if records.len() >= max_records {
+                return (records, offset);
+            }
+            offset += line.len();
+            let line = line.strip_suffix('\n').unwrap_or(line);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            records.extend(self.process_line(line).into_inner().ok());
+            while let Some(output) = self.take_deferred_output() {
+                records.extend(output.ok());
+            }
+        }
+        if records.len() < max_records {
+            records.extend(self.finish());
+        }
+        (records, offset)
+    }
+
+    /// True if fields have been accumulated for a record that has not yet been emitted,
+    /// in which case [RecordParser::end_input] will return a record.
+    pub fn record_in_progress(&self) -> bool {
+        self.current_field_count() > 0
+    }
+
+    /// The number of fields accumulated for the record in progress.
+    pub fn current_field_count(&self) -> usize {
+        self.record_emitter.field_count()
+    }
+
+    /// Take the keyless lines seen since the last call, with their line numbers.
+    ///
+    /// Useful to report all malformed lines after parsing a record.
+    pub fn take_keyless_lines(&mut self) -> Vec<LineNumber<String>> {
+        core::mem::take(&mut self.keyless_lines)
+    }
+}
+
+/// Keys for fields of a [Record] that may be given positionally, as keyless lines,
+/// in relaxed human-written records.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordSchema {
+    positional_keys: Vec<String>,
+}
+
+impl RecordSchema {
+    /// Create a schema whose positional fields have the given keys, in order.
+    pub fn new(positional_keys: &[&str]) -> Self {
+        Self {
+            positional_keys: positional_keys.iter().map(|key| key.to_string()).collect(),
+        }
+    }
+
+    /// Assign keyless lines, such as from [RecordParser::take_keyless_lines],
+    /// in order to the positional keys not already present in the record,
+    /// appending a field for each. The lines are trimmed.
+    ///
+    /// For example, with the keys `Name` and `Version`, a record with a `Name` field
+    /// and the keyless line `1.0` gains the field `Version: 1.0`.
+    /// Returns the number of lines assigned: any further lines are ignored.
+    pub fn apply_positional(
+        &self,
+        record: &mut Record,
+        keyless_lines: &[LineNumber<String>],
+    ) -> usize {
+        let missing: Vec<&String> = self
+            .positional_keys
+            .iter()
+            .filter(|key| record.count_fields_with_key(key) == 0)
+            .collect();
+        let assigned = missing.len().min(keyless_lines.len());
+        for (key, line) in missing.into_iter().zip(keyless_lines) {
+            record.fields.push(KeyValuePair {
+                key: key.clone(),
+                value: line.value().trim().to_string(),
+            });
+            if !record.line_numbers.is_empty() {
+                record.line_numbers.push(Some(line.line_number()));
+            }
+        }
+        assigned
+    }
+}
+
+/// Serialize records as a sequence, such as a JSON array, using the representation
+/// of [Record::to_json] for each.
+///
+/// Records are serialized one at a time as the iterator yields them, without collecting them first,
+/// so a large document can be converted with bounded memory,
+/// for example by passing the records from a [RecordParser] as they are parsed.
+#[cfg(feature = "serde")]
+pub fn serialize_records<S, I>(records: I, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    I: IntoIterator<Item = Record>,
+{
+    use serde::ser::SerializeSeq;
+    let mut seq = serializer.serialize_seq(None)?;
+    for record in records {
+        seq.serialize_element(&record.to_json())?;
     }
+    seq.end()
 }
 
-impl<R: RecordEmitter + Default, P: ParsePolicy> RecordParser<R, P>
+/// Parse a whole string into records separated by empty lines, using a default-constructed policy.
+///
+/// Keyless lines are ignored: use [RecordParser] directly for more control.
+pub fn parse_records_str<P: ParsePolicy + Default>(input: &str) -> Vec<Record> {
+    let mut parser: RecordParser<BlankLineRecordEmitter, P> =
+        RecordParser::new(BlankLineRecordEmitter::default(), KVParser::default());
+    let mut records = vec![];
+    for line in input.lines() {
+        records.extend(parser.process_line(line).ok());
+        while let Some(output) = parser.take_deferred_output() {
+            records.extend(output.ok());
+        }
+    }
+    records.extend(parser.finish());
+    records
+}
+
+/// Parse a single record, ending at the first empty line after its fields, from the start of a string.
+///
+/// Returns the record, if any, and the rest of the string after the empty line ending the record,
+/// for example to pass a body following a header to another parser.
+pub fn parse_one_record<P: ParsePolicy>(policy: P, input: &str) -> (Option<Record>, &str) {
+    let parser: RecordParser<BlankLineRecordEmitter, P> =
+        RecordParser::new(BlankLineRecordEmitter::default(), KVParser::new(policy));
+    let (records, offset) = parser.parse_str_limited(input, 1);
+    (records.into_iter().next(), &input[offset..])
+}
+
+impl<R: RecordEmitter + Default, P: ParsePolicy> Default for RecordParser<R, P>
 where
     KVParser<P>: Default,
 {
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self {
             inner: KVParser::default(),
             record_emitter: R::default(),
+            keyless_lines: vec![],
+            type_line: false,
+            pending_type: None,
+            line_numbers: false,
+            field_lines: vec![],
+            deferred: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    // With the derive feature, the macro is imported along with the trait below.
+    #[cfg(not(feature = "derive"))]
+    use key_value_parser_derive::FromRecord;
+
+    use super::{
+        FieldSetter, FromRecord, MergeReport, MergeStrategy, Record, RecordError, RecordParser,
+        RecordSchema,
+    };
+    use crate::{
+        emitters::{BlankLineRecordEmitter, DedupMode, DedupRecordEmitter},
+        policies::{AppendAwarePolicy, IndentScopePolicy, KeyValueOp, SPDXParsePolicy},
+        KVParser, KeyValuePair, LineNumber, Output, ParserOutput,
+    };
+    use std::collections::HashSet;
+
+    fn pair(key: &str, value: &str) -> KeyValuePair {
+        KeyValuePair {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[derive(Debug, FromRecord)]
+    struct Document {
+        #[kv(key = "SPDXVersion")]
+        spdx_version: String,
+        #[kv(key = "DocumentComment")]
+        comment: Option<String>,
+        revision: Option<u32>,
+    }
+
+    #[test]
+    fn derive_from_record() {
+        let record = Record::new(vec![pair("SPDXVersion", "SPDX-2.2"), pair("revision", "3")]);
+        let doc = Document::from_record(&record).unwrap();
+        assert_eq!(doc.spdx_version, "SPDX-2.2");
+        assert_eq!(doc.comment, None);
+        assert_eq!(doc.revision, Some(3));
+
+        let record = Record::new(vec![pair("DocumentComment", "comment")]);
+        assert!(matches!(
+            Document::from_record(&record),
+            Err(RecordError::MissingField(key)) if key == "SPDXVersion"
+        ));
+
+        let record = Record::new(vec![pair("SPDXVersion", "SPDX-2.2"), pair("revision", "x")]);
+        assert!(matches!(
+            Document::from_record(&record),
+            Err(RecordError::Message(_))
+        ));
+    }
+
+    #[test]
+    fn eq_unordered() {
+        let a = Record::new(vec![pair("a", "1"), pair("b", "2"), pair("a", "3")]);
+        let b = Record::new(vec![pair("a", "3"), pair("a", "1"), pair("b", "2")]);
+        assert_ne!(a, b);
+        assert!(a.eq_unordered(&b));
+        assert!(a.eq_unordered(&a.clone()));
+
+        let c = Record::new(vec![pair("a", "1"), pair("b", "2"), pair("b", "2")]);
+        assert!(!a.eq_unordered(&c));
+        let d = Record::new(vec![pair("a", "1"), pair("b", "2")]);
+        assert!(!a.eq_unordered(&d));
+    }
+
+    #[test]
+    fn take_keyless_lines() {
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default();
+        for line in [
+            "key: value",
+            "oops",
+            "key: <text>a",
+            "not keyless</text>",
+            "again",
+        ] {
+            parser.process_line(line);
         }
+        assert_eq!(
+            parser.take_keyless_lines(),
+            vec![
+                LineNumber::new(2, "oops".to_string()),
+                LineNumber::new(5, "again".to_string())
+            ]
+        );
+        assert!(parser.take_keyless_lines().is_empty());
+    }
+
+    #[test]
+    fn from_vec_and_as_slice() {
+        let fields = vec![pair("a", "1"), pair("b", "2")];
+        let record = Record::from(fields.clone());
+        assert_eq!(record.as_slice(), &fields[..]);
+        assert_eq!(record.as_pairs(), &fields[..]);
+        assert_eq!(
+            record.to_pairs_vec(),
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("b".to_string(), "2".to_string())
+            ]
+        );
+        let slice: &[KeyValuePair] = record.as_ref();
+        assert_eq!(slice, &fields[..]);
+        assert_eq!(record.pairs(), &fields[..]);
+        assert_eq!(record.clone().into_pairs(), fields);
+        assert_eq!(fields.iter().cloned().collect::<Record>(), record);
+        assert_eq!(Vec::from(record), fields);
+    }
+
+    #[test]
+    fn merge() {
+        let defaults = Record::new(vec![
+            pair("a", "1"),
+            pair("b", "2"),
+            pair("c", "3"),
+            pair("b", "4"),
+        ]);
+        let user = Record::new(vec![
+            pair("d", "5"),
+            pair("b", "6"),
+            pair("a", "7"),
+            pair("a", "8"),
+        ]);
+
+        let mut merged = defaults.clone();
+        merged.merge(user.clone(), MergeStrategy::Append);
+        assert_eq!(merged.as_slice().len(), 8);
+        assert_eq!(merged.as_slice()[4], ("d", "5"));
+
+        let mut merged = defaults.clone();
+        merged.merge(user.clone(), MergeStrategy::Override);
+        assert_eq!(
+            merged,
+            Record::new(vec![
+                pair("a", "7"),
+                pair("b", "6"),
+                pair("c", "3"),
+                pair("d", "5"),
+                pair("a", "8")
+            ])
+        );
+
+        let mut reported = defaults.clone();
+        let report = reported.merge_with_report(user.clone());
+        assert_eq!(reported, merged);
+        let triple =
+            |k: &str, old: &str, new: &str| (k.to_string(), old.to_string(), new.to_string());
+        assert_eq!(
+            report,
+            MergeReport {
+                overridden: vec![triple("a", "1", "7"), triple("b", "2", "6")],
+                added: vec![pair("d", "5"), pair("a", "8")],
+                removed: vec![pair("b", "4")],
+                untouched: vec!["c".to_string()],
+            }
+        );
+
+        let mut merged = defaults.clone();
+        merged.merge(user, MergeStrategy::KeepFirst);
+        let mut expected = defaults.into_inner();
+        expected.push(pair("d", "5"));
+        assert_eq!(merged, Record::new(expected));
+    }
+
+    #[test]
+    fn blank_lines_make_no_records() {
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default();
+        for line in ["", "  ", ""] {
+            assert_eq!(parser.process_line(line).into_inner(), Output::EmptyLine);
+        }
+        assert_eq!(parser.end_input(), Output::EmptyLine);
+
+        let record = parser.process_line("a: 1");
+        assert!(record.value().is_pending());
+        let record = parser.process_line("").into_inner().ok().unwrap();
+        assert!(!record.is_empty());
+        assert_eq!(parser.process_line("").into_inner(), Output::EmptyLine);
+        assert!(Record::default().is_empty());
+    }
+
+    #[test]
+    fn parse_records_str() {
+        let records = super::parse_records_str::<SPDXParsePolicy>(
+            "\n\na: 1\nb: <text>2\n</text>\n\n\nc: 3\nkeyless\nd: <text>4",
+        );
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].as_slice(), [("a", "1"), ("b", "2\n")]);
+        assert_eq!(records[1].as_slice(), [("c", "3"), ("d", "4")]);
+        assert!(super::parse_records_str::<SPDXParsePolicy>("").is_empty());
+    }
+
+    #[test]
+    fn record_parser_aborted_value() {
+        let records = super::parse_records_str::<IndentScopePolicy>("a:\n  x\nb: 1\nc: 2\n");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].as_slice(), [("a", "x"), ("b", "1"), ("c", "2")]);
+
+        let (record, rest) = super::parse_one_record(IndentScopePolicy, "a:\n  x\nb: 1\n\nc: 2");
+        assert_eq!(record.unwrap().as_slice(), [("a", "x"), ("b", "1")]);
+        assert_eq!(rest, "c: 2");
+    }
+
+    #[test]
+    fn parse_one_record() {
+        let input = "\nContent-Type: <text>a\n\nb</text>\r\nLength: 3\r\n\r\nbody\n\nmore: body";
+        let (record, rest) = super::parse_one_record(SPDXParsePolicy {}, input);
+        assert_eq!(
+            record.unwrap().as_slice(),
+            [("Content-Type", "a\n\nb"), ("Length", "3")]
+        );
+        assert_eq!(rest, "body\n\nmore: body");
+
+        let (record, rest) = super::parse_one_record(SPDXParsePolicy {}, "a: 1");
+        assert_eq!(record.unwrap().as_slice(), [("a", "1")]);
+        assert_eq!(rest, "");
+
+        let (record, rest) = super::parse_one_record(SPDXParsePolicy {}, "\n\n");
+        assert_eq!(record, None);
+        assert_eq!(rest, "");
+    }
+
+    #[test]
+    fn record_in_progress() {
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default();
+        assert!(!parser.record_in_progress());
+        parser.process_line("a: 1");
+        parser.process_line("b: <text>2");
+        assert!(parser.record_in_progress());
+        assert_eq!(parser.current_field_count(), 1);
+        parser.process_line("2</text>");
+        assert_eq!(parser.current_field_count(), 2);
+        assert!(parser.process_line("").value().is_some());
+        assert!(!parser.record_in_progress());
+        assert_eq!(parser.current_field_count(), 0);
+    }
+
+    #[test]
+    fn finish() {
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default().with_line_numbers();
+        assert_eq!(parser.finish(), None);
+        parser.process_line("a: 1");
+        parser.process_line("b: <text>2");
+        let record = parser.finish().unwrap();
+        assert_eq!(record, Record::new(vec![pair("a", "1"), pair("b", "2")]));
+        assert_eq!(record.line_for_key("b"), Some(2));
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn parse_str_limited() {
+        type Parser = RecordParser<BlankLineRecordEmitter, SPDXParsePolicy>;
+        let input = "a: 1\nb: 2\n\n\na: 3\r\n\na: <text>4";
+        let (records, offset) = Parser::default().parse_str_limited(input, 1);
+        assert_eq!(
+            records,
+            vec![Record::new(vec![pair("a", "1"), pair("b", "2")])]
+        );
+        assert_eq!(&input[offset..], "\na: 3\r\n\na: <text>4");
+
+        let (records, offset) = Parser::default().parse_str_limited(input, 2);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1], Record::new(vec![pair("a", "3")]));
+        assert_eq!(&input[offset..], "a: <text>4");
+
+        let (records, offset) = Parser::default().parse_str_limited(input, 5);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2], Record::new(vec![pair("a", "4")]));
+        assert_eq!(offset, input.len());
+
+        let (records, offset) = Parser::default().parse_str_limited(input, 0);
+        assert!(records.is_empty());
+        assert_eq!(offset, 0);
+    }
+
+    #[test]
+    fn group_by_key() {
+        let record = Record::new(vec![
+            pair("b", "1"),
+            pair("a", "2"),
+            pair("b", "3"),
+            pair("c", "4"),
+            pair("a", "5"),
+        ]);
+        assert_eq!(
+            record.group_by_key(),
+            vec![
+                ("b", vec!["1", "3"]),
+                ("a", vec!["2", "5"]),
+                ("c", vec!["4"])
+            ]
+        );
+        assert!(Record::default().group_by_key().is_empty());
+        assert_eq!(record.keys().collect::<Vec<_>>(), vec!["b", "a", "c"]);
+        assert_eq!(record.distinct_key_count(), 3);
+        assert_eq!(Record::default().distinct_key_count(), 0);
+    }
+
+    #[test]
+    fn type_line() {
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default().with_type_line(true);
+        assert!(parser.process_line("@Person").value().is_pending());
+        parser.process_line("name: Alice");
+        assert_eq!(
+            parser.process_line("keyless").into_inner(),
+            Output::KeylessLine("keyless".to_string())
+        );
+        let record = parser.process_line("").into_inner().ok().unwrap();
+        assert_eq!(record.record_type(), Some("@Person"));
+        assert_eq!(record.as_slice(), &[pair("name", "Alice")]);
+
+        parser.process_line("name: Bob");
+        assert_eq!(
+            parser.process_line("@Late").into_inner(),
+            Output::KeylessLine("@Late".to_string())
+        );
+        let record = parser.end_input().ok().unwrap();
+        assert_eq!(record.record_type(), None);
+
+        parser.process_line("@Empty");
+        let record = parser.process_line("").into_inner().ok().unwrap();
+        assert_eq!(record.record_type(), Some("@Empty"));
+        assert!(record.as_slice().is_empty());
+
+        assert_eq!(
+            parser.take_keyless_lines(),
+            vec![
+                LineNumber::new(3, "keyless".to_string()),
+                LineNumber::new(6, "@Late".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn read_into() {
+        #[derive(Default)]
+        struct Package {
+            name: String,
+            version: String,
+        }
+        let setters: &[(&str, FieldSetter<Package>)] = &[
+            ("PackageName", |p, v| p.name = v.to_string()),
+            ("PackageVersion", |p, v| p.version = v.to_string()),
+        ];
+        let record = Record::new(vec![
+            pair("PackageVersion", "1.0"),
+            pair("PackageName", "example"),
+        ]);
+        let package = record.read_into(Package::default(), setters).unwrap();
+        assert_eq!(package.name, "example");
+        assert_eq!(package.version, "1.0");
+
+        let record = Record::new(vec![pair("PackageName", "example")]);
+        assert!(matches!(
+            record.read_into(Package::default(), setters),
+            Err(RecordError::MissingField(key)) if key == "PackageVersion"
+        ));
+    }
+
+    #[test]
+    fn positional_access() {
+        let record = Record::new(vec![pair("Type", "a"), pair("Name", "b"), pair("Sum", "c")]);
+        assert_eq!(record.first().unwrap(), &("Type", "a"));
+        assert_eq!(record.last().unwrap(), &("Sum", "c"));
+        assert_eq!(record.field(1).unwrap(), &("Name", "b"));
+        assert_eq!(record.field(3), None);
+        assert_eq!(Record::default().first(), None);
+        assert_eq!(Record::default().last(), None);
+    }
+
+    #[test]
+    fn schema_apply_positional() {
+        let schema = RecordSchema::new(&["Name", "Version", "License"]);
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default().with_line_numbers();
+        let mut record = None;
+        for line in ["widget", "Version: 2", " 1.0 ", "MIT", "extra", ""] {
+            record = record.or(parser.process_line(line).ok());
+        }
+        let mut record = record.unwrap();
+        let keyless_lines = parser.take_keyless_lines();
+        assert_eq!(schema.apply_positional(&mut record, &keyless_lines), 2);
+        assert_eq!(
+            record,
+            Record::new(vec![
+                pair("Version", "2"),
+                pair("Name", "widget"),
+                pair("License", "1.0"),
+            ])
+        );
+        assert_eq!(record.line_for_key("License"), Some(3));
+    }
+
+    #[test]
+    fn apply_ops() {
+        let mut parser: KVParser<AppendAwarePolicy> = KVParser::default();
+        let ops: Vec<KeyValueOp> = "b=3\na+=2\nc+=4\nb+=5\nb=6"
+            .lines()
+            .filter_map(|line| parser.process_line_op(line).ok())
+            .collect();
+        let mut record = Record::new(vec![pair("a", "1"), pair("b", "1"), pair("b", "2")]);
+        record.apply_ops(ops);
+        assert_eq!(
+            record,
+            Record::new(vec![
+                pair("a", "1"),
+                pair("b", "6"),
+                pair("a", "2"),
+                pair("c", "4"),
+            ])
+        );
+    }
+
+    #[test]
+    fn validate_keys_in_set() {
+        let record = Record::new(vec![
+            pair("a", "1"),
+            pair("x", "2"),
+            pair("b", "3"),
+            pair("x", "4"),
+        ]);
+        let allowed: HashSet<&str> = IntoIterator::into_iter(["a", "b"]).collect();
+        let errors = record.validate_keys_in_set(&allowed).unwrap_err();
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["Unexpected field x", "Unexpected field x"]
+        );
+        let allowed: HashSet<&str> = IntoIterator::into_iter(["a", "b", "x"]).collect();
+        assert!(record.validate_keys_in_set(&allowed).is_ok());
+        assert!(Record::default()
+            .validate_keys_in_set(&HashSet::new())
+            .is_ok());
+    }
+
+    #[test]
+    fn typed_values() {
+        let record = Record::new(vec![
+            pair("n", "42"),
+            pair("bad", "x"),
+            pair("yes", "Yes"),
+            pair("zero", "0"),
+            pair("dup", "1"),
+            pair("dup", "2"),
+        ]);
+        assert_eq!(record.get_parsed::<u32>("n").unwrap(), Some(42));
+        assert_eq!(record.get_parsed::<u32>("missing").unwrap(), None);
+        assert_eq!(
+            record.get_parsed::<u32>("bad").unwrap_err().to_string(),
+            "Other error message: Could not parse value of field bad: invalid digit found in string"
+        );
+        assert!(matches!(
+            record.get_parsed::<u32>("dup"),
+            Err(RecordError::WantedAtMostOneFoundMore(_, 2))
+        ));
+        assert_eq!(record.get_bool("yes").unwrap(), Some(true));
+        assert_eq!(record.get_bool("zero").unwrap(), Some(false));
+        assert_eq!(record.get_bool("missing").unwrap(), None);
+        assert!(record.get_bool("n").is_err());
+    }
+
+    #[test]
+    fn rename_key() {
+        let mut record = Record::new(vec![pair("old", "1"), pair("b", "2"), pair("old", "3")]);
+        assert_eq!(record.rename_key("old", "new"), 2);
+        assert_eq!(
+            record,
+            Record::new(vec![pair("new", "1"), pair("b", "2"), pair("new", "3")])
+        );
+        assert_eq!(record.rename_key("old", "new"), 0);
+    }
+
+    #[test]
+    fn expand_variables() {
+        let mut record = Record::new(vec![
+            pair("HOME", "/home/${USER}"),
+            pair("USER", "me"),
+            pair("PATH", "${HOME}/bin:${UNKNOWN}:${BROKEN"),
+            pair("A", "${B}"),
+            pair("B", "x${A}"),
+            pair("SELF", "${SELF}"),
+            pair("USER", "you"),
+        ]);
+        record.expand_variables();
+        assert_eq!(
+            record.into_inner(),
+            vec![
+                pair("HOME", "/home/you"),
+                pair("USER", "me"),
+                pair("PATH", "/home/you/bin:${UNKNOWN}:${BROKEN"),
+                pair("A", "x${A}"),
+                pair("B", "x${B}"),
+                pair("SELF", "${SELF}"),
+                pair("USER", "you"),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_duplicate_keys() {
+        let record = Record::new(vec![
+            pair("b", "1"),
+            pair("a", "2"),
+            pair("c", "3"),
+            pair("a", "4"),
+            pair("b", "5"),
+            pair("a", "6"),
+        ]);
+        assert_eq!(record.find_duplicate_keys(), vec![("b", 2), ("a", 3)]);
+        assert!(Record::new(vec![pair("a", "1")])
+            .find_duplicate_keys()
+            .is_empty());
+    }
+
+    #[test]
+    fn line_numbers() {
+        let input = "a: 1\nb: <text>x\ny</text>\n\n\n@Type\nc: 3\nc: 4\nd: 5";
+        let mut parser: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy> =
+            RecordParser::default()
+                .with_line_numbers()
+                .with_type_line(true);
+        let mut records: Vec<Record> = input
+            .lines()
+            .filter_map(|line| parser.process_line(line).into_inner().ok())
+            .collect();
+        records.extend(parser.finish());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].line_for_key("a"), Some(1));
+        assert_eq!(records[0].line_for_key("b"), Some(3));
+        assert_eq!(records[0].line_for_key("z"), None);
+        let lines: Vec<_> = records[1]
+            .iter_with_line_numbers()
+            .map(|(line, pair)| (line, pair.value.as_str()))
+            .collect();
+        assert_eq!(lines, vec![(Some(7), "3"), (Some(8), "4"), (Some(9), "5")]);
+        // Line numbers are not compared.
+        assert_eq!(
+            records[0],
+            Record::new(vec![pair("a", "1"), pair("b", "x\ny")])
+        );
+
+        // Fields are matched up with their lines after the emitter removes some.
+        let mut parser = RecordParser::new(
+            DedupRecordEmitter::new(DedupMode::KeepLast),
+            KVParser::<SPDXParsePolicy>::default(),
+        )
+        .with_line_numbers();
+        for line in ["a: 1", "b: 2", "a: 3"] {
+            parser.process_line(line);
+        }
+        let record = parser.end_input().ok().unwrap();
+        let lines: Vec<_> = record
+            .iter_with_line_numbers()
+            .map(|(line, _)| line)
+            .collect();
+        assert_eq!(lines, vec![Some(2), Some(3)]);
+
+        // Without the option, line numbers are unknown.
+        let record = super::parse_records_str::<SPDXParsePolicy>("a: 1");
+        assert_eq!(record[0].line_for_key("a"), None);
+    }
+
+    #[test]
+    fn collect_indexed() {
+        let record = Record::new(vec![
+            pair("Item[1]", "b"),
+            pair("Item.0", "a"),
+            pair("Item[x]", "malformed"),
+            pair("Item[]", "malformed"),
+            pair("Item[+2]", "malformed"),
+            pair("Items[3]", "other"),
+            pair("Item[10]", "c"),
+        ]);
+        assert_eq!(record.collect_indexed("Item"), vec!["a", "b", "c"]);
+        assert!(record.collect_indexed("Missing").is_empty());
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn into_index_map() {
+        let record = Record::new(vec![pair("b", "1"), pair("a", "2"), pair("b", "3")]);
+        let map = record.into_index_map();
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("b".to_string(), vec!["1".to_string(), "3".to_string()]),
+                ("a".to_string(), vec!["2".to_string()]),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn to_json() {
+        let record = Record::new(vec![pair("Name", "a"), pair("Tag", "x"), pair("Tag", "y")]);
+        assert_eq!(
+            record.to_json(),
+            serde_json::json!({"Name": "a", "Tag": ["x", "y"]})
+        );
+        assert_eq!(Record::default().to_json(), serde_json::json!({}));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serialize_records() {
+        let records =
+            super::parse_records_str::<SPDXParsePolicy>("Name: a\nTag: x\nTag: y\n\nName: b\n");
+        let mut json = vec![];
+        super::serialize_records(records, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"[{"Name":"a","Tag":["x","y"]},{"Name":"b"}]"#
+        );
     }
 }
@@ -15,4 +15,15 @@ pub trait RecordEmitter {
 
     /// Signal the end of input, returning the record in progress if any.
     fn end_input(&mut self) -> Output<Vec<KeyValuePair>>;
+
+    /// The number of fields accumulated for the record in progress, which has not yet been emitted.
+    ///
+    /// The default implementation returns 0, meaning unknown: implement this to support
+    /// [RecordParser::current_field_count](crate::record::RecordParser::current_field_count),
+    /// [RecordParser::with_type_line](crate::record::RecordParser::with_type_line) and
+    /// [RecordParser::with_line_numbers](crate::record::RecordParser::with_line_numbers)
+    /// with this emitter.
+    fn field_count(&self) -> usize {
+        0
+    }
 }
@@ -0,0 +1,170 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Choose a policy for [KVParser] based on file extension, using [PolicyRegistry]
+
+use std::{collections::HashMap, fmt::Debug, path::Path};
+
+use crate::{
+    parse_policy::{BoxedPolicy, DynPolicy, ParsePolicy},
+    policies::{PropertiesPolicy, SPDXParsePolicy, TrivialParsePolicy, TEXT_OPEN_TAG},
+    KVParser,
+};
+
+type PolicyFactory = Box<dyn Fn() -> BoxedPolicy>;
+
+/// The number of lines of a sample examined by [detect_policy].
+const DETECT_LINES: usize = 50;
+
+/// Guess which bundled policy best fits a sample of a document, such as its first few lines,
+/// returning its extension in the default [PolicyRegistry]: `"spdx"` or `"properties"`,
+/// or `"trivial"` for [TrivialParsePolicy].
+///
+/// Only the first lines of the sample are examined.
+/// It is SPDX if any line has a `<text>` marker or an `SPDXVersion` key.
+/// Otherwise, it is `.properties` if more lines are split by `=` than by `: `.
+pub fn detect_policy(sample: &str) -> &'static str {
+    let mut colon_lines = 0;
+    let mut equals_lines = 0;
+    for line in sample.lines().take(DETECT_LINES) {
+        let line = line.trim_start();
+        if line.contains(TEXT_OPEN_TAG) || line.starts_with("SPDXVersion:") {
+            return "spdx";
+        }
+        if line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        match (line.find(": "), line.find('=')) {
+            (Some(colon), Some(equals)) if equals < colon => equals_lines += 1,
+            (Some(_), _) => colon_lines += 1,
+            (None, Some(_)) => equals_lines += 1,
+            (None, None) => {}
+        }
+    }
+    if equals_lines > colon_lines {
+        "properties"
+    } else {
+        "trivial"
+    }
+}
+
+/// A mapping from file extensions to the policies used to parse those files.
+///
+/// The default registry contains the bundled policies:
+///
+/// - `spdx`: [SPDXParsePolicy]
+/// - `properties`: [PropertiesPolicy]
+pub struct PolicyRegistry {
+    factories: HashMap<String, PolicyFactory>,
+}
+
+impl PolicyRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self {
+            factories: HashMap::new(),
+        }
+    }
+
+    /// Register a policy for an extension (without the leading `.`), replacing any existing policy.
+    ///
+    /// Extensions are matched case-insensitively.
+    pub fn register<P: ParsePolicy + Clone + 'static>(&mut self, extension: &str, policy: P) {
+        self.factories.insert(
+            extension.to_ascii_lowercase(),
+            Box::new(move || DynPolicy::boxed(policy.clone())),
+        );
+    }
+
+    /// Get a new instance of the policy registered for an extension, if any.
+    pub fn policy_for_extension(&self, extension: &str) -> Option<BoxedPolicy> {
+        self.factories
+            .get(&extension.to_ascii_lowercase())
+            .map(|factory| factory())
+    }
+
+    /// Get a new instance of the policy that best fits a sample of a document,
+    /// as guessed by [detect_policy], if it is registered,
+    /// or [TrivialParsePolicy] otherwise.
+    pub fn detect_policy(&self, sample: &str) -> BoxedPolicy {
+        self.policy_for_extension(detect_policy(sample))
+            .unwrap_or_else(|| DynPolicy::boxed(TrivialParsePolicy {}))
+    }
+
+    /// Create a parser using the policy registered for the extension of a path, if any.
+    pub fn parser_for_path<T: AsRef<Path>>(&self, path: T) -> Option<KVParser<BoxedPolicy>> {
+        let extension = path.as_ref().extension()?.to_str()?;
+        self.policy_for_extension(extension).map(KVParser::new)
+    }
+}
+
+impl Default for PolicyRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register("spdx", SPDXParsePolicy {});
+        registry.register("properties", PropertiesPolicy {});
+        registry
+    }
+}
+
+impl Debug for PolicyRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut extensions: Vec<_> = self.factories.keys().collect();
+        extensions.sort();
+        f.debug_struct("PolicyRegistry")
+            .field("extensions", &extensions)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect_policy, PolicyRegistry};
+    use crate::{policies::TrivialParsePolicy, ParserOutput};
+
+    #[test]
+    fn registry() {
+        let mut registry = PolicyRegistry::default();
+        let mut parser = registry.parser_for_path("dir/doc.SPDX").unwrap();
+        assert_eq!(
+            parser.process_line("key: <text>value</text>").ok().unwrap(),
+            ("key", "value")
+        );
+
+        let mut parser = registry.parser_for_path("app.properties").unwrap();
+        assert_eq!(
+            parser.process_line("key=value").ok().unwrap(),
+            ("key", "value")
+        );
+
+        assert!(registry.parser_for_path("notes.txt").is_none());
+        assert!(registry.parser_for_path("settings.ini").is_none());
+        assert!(registry.parser_for_path("no_extension").is_none());
+
+        registry.register("txt", TrivialParsePolicy {});
+        let mut parser = registry.parser_for_path("notes.txt").unwrap();
+        assert_eq!(
+            parser.process_line("key: <text>value</text>").ok().unwrap(),
+            ("key", "<text>value</text>")
+        );
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(
+            detect_policy("SPDXVersion: SPDX-2.2\nDataLicense: CC0-1.0"),
+            "spdx"
+        );
+        assert_eq!(detect_policy("a: b\nc: <text>d\ne</text>"), "spdx");
+        assert_eq!(
+            detect_policy("# comment: here\na=b\nurl=http://x\nc: d"),
+            "properties"
+        );
+        assert_eq!(detect_policy("a: b=c\nd: e"), "trivial");
+        assert_eq!(detect_policy(""), "trivial");
+
+        let mut parser = crate::KVParser::new(PolicyRegistry::default().detect_policy("a=b"));
+        assert_eq!(parser.process_line("a=b").ok().unwrap(), ("a", "b"));
+    }
+}
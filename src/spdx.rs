@@ -0,0 +1,149 @@
+// Copyright 2021, Collabora, Ltd.
+//
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Parse SPDX tag-value documents into records using [SpdxTagValueParser]
+
+use std::io::BufRead;
+
+use crate::{
+    emitters::BlankLineRecordEmitter,
+    policies::SPDXParsePolicy,
+    record::{Record, RecordParser},
+    LineNumber, Output, ParserOutput,
+};
+
+/// A parser for SPDX tag-value documents, combining [SPDXParsePolicy] for `<text>` values
+/// with [BlankLineRecordEmitter] to split the document into records at blank lines.
+///
+/// Each section of the document (document creation information, package and file information,
+/// annotations, relationships, etc.) separated by blank lines becomes a [Record].
+#[derive(Debug, Default)]
+pub struct SpdxTagValueParser {
+    inner: RecordParser<BlankLineRecordEmitter, SPDXParsePolicy>,
+}
+
+impl SpdxTagValueParser {
+    /// Create a parser.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass a line to process and advance the state of the parser.
+    ///
+    /// If a record has finished, it will be found in the return value.
+    /// Any other records finished by the line are available from
+    /// [SpdxTagValueParser::take_deferred_output].
+    pub fn process_line(&mut self, line: &str) -> LineNumber<Output<Record>> {
+        self.inner.process_line(line)
+    }
+
+    /// Take the next of any other records completed by the most recent line, in order,
+    /// as described for [RecordParser::take_deferred_output].
+    pub fn take_deferred_output(&mut self) -> Option<LineNumber<Output<Record>>> {
+        self.inner.take_deferred_output()
+    }
+
+    /// End the input, returning any record in progress.
+    pub fn finish(&mut self) -> Option<Record> {
+        self.inner.finish()
+    }
+
+    /// Take the keyless lines (such as comments) seen since the last call, with their line numbers.
+    pub fn take_keyless_lines(&mut self) -> Vec<LineNumber<String>> {
+        self.inner.take_keyless_lines()
+    }
+
+    /// Parse a whole document from a string.
+    pub fn parse_str(mut self, input: &str) -> Vec<Record> {
+        let mut records = vec![];
+        for line in input.lines() {
+            records.extend(self.process_line(line).ok());
+            while let Some(output) = self.take_deferred_output() {
+                records.extend(output.ok());
+            }
+        }
+        records.extend(self.finish());
+        records
+    }
+
+    /// Parse a whole document from a reader.
+    pub fn parse_reader<R: BufRead>(mut self, reader: R) -> std::io::Result<Vec<Record>> {
+        let mut records = vec![];
+        for line in reader.lines() {
+            records.extend(self.process_line(&line?).ok());
+            while let Some(output) = self.take_deferred_output() {
+                records.extend(output.ok());
+            }
+        }
+        records.extend(self.finish());
+        Ok(records)
+    }
+}
+
+/// Split the value of a checksum field, such as `FileChecksum: SHA1: abc123`,
+/// into the algorithm and hex digest, such as `("SHA1", "abc123")`.
+///
+/// Returns `None` if the value is not of the form `ALGORITHM: digest`.
+pub fn parse_spdx_checksum(value: &str) -> Option<(&str, &str)> {
+    let (algorithm, digest) = value.split_once(": ")?;
+    let (algorithm, digest) = (algorithm.trim(), digest.trim());
+    if algorithm.is_empty() || digest.is_empty() {
+        None
+    } else {
+        Some((algorithm, digest))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_spdx_checksum, SpdxTagValueParser};
+
+    const DOCUMENT: &str = "SPDXVersion: SPDX-2.2
+DataLicense: CC0-1.0
+DocumentComment: <text>A document
+
+with a blank line</text>
+
+## Package
+PackageName: example
+PackageVersion: 1.0
+
+Relationship: SPDXRef-DOCUMENT DESCRIBES SPDXRef-Package
+FileComment: <text>Unterminated";
+
+    #[test]
+    fn parse_document() {
+        let records = SpdxTagValueParser::new().parse_str(DOCUMENT);
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records[0]
+                .value_for_required_key("DocumentComment")
+                .unwrap(),
+            "A document\n\nwith a blank line"
+        );
+        assert_eq!(records[1].as_slice().len(), 2);
+        assert_eq!(records[1].as_slice()[0], ("PackageName", "example"));
+        assert_eq!(
+            records[2].value_for_required_key("FileComment").unwrap(),
+            "Unterminated"
+        );
+
+        let from_reader = SpdxTagValueParser::new()
+            .parse_reader(DOCUMENT.as_bytes())
+            .unwrap();
+        assert_eq!(from_reader, records);
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(
+            parse_spdx_checksum("SHA1: d6a770ba38583ed4bb4525bd96e50461655d2758"),
+            Some(("SHA1", "d6a770ba38583ed4bb4525bd96e50461655d2758"))
+        );
+        assert_eq!(parse_spdx_checksum(" MD5:  abc "), Some(("MD5", "abc")));
+        assert_eq!(parse_spdx_checksum("SHA1 abc"), None);
+        assert_eq!(parse_spdx_checksum("SHA1: "), None);
+        assert_eq!(parse_spdx_checksum(": abc"), None);
+    }
+}
@@ -9,6 +9,161 @@ pub struct KeyValuePair {
     pub value: String,
 }
 
+impl KeyValuePair {
+    /// Split the value on a separator, trimming whitespace from each element.
+    ///
+    /// An empty or whitespace-only value produces no elements,
+    /// but otherwise empty elements (such as after a trailing separator) are kept:
+    /// see [KeyValuePair::split_value_trimmed_nonempty] to drop them.
+    pub fn split_value(&self, sep: &str) -> Vec<&str> {
+        if self.value.trim().is_empty() {
+            return vec![];
+        }
+        self.value.split(sep).map(str::trim).collect()
+    }
+
+    /// Like [KeyValuePair::split_value], but elements that are empty after trimming are dropped.
+    pub fn split_value_trimmed_nonempty(&self, sep: &str) -> Vec<&str> {
+        self.value
+            .split(sep)
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+            .collect()
+    }
+}
+
+impl PartialEq<(&str, &str)> for KeyValuePair {
+    fn eq(&self, other: &(&str, &str)) -> bool {
+        self.key == other.0 && self.value == other.1
+    }
+}
+
+impl PartialEq<(&str, String)> for KeyValuePair {
+    fn eq(&self, other: &(&str, String)) -> bool {
+        self.key == other.0 && self.value == other.1
+    }
+}
+
+impl PartialEq<(String, String)> for KeyValuePair {
+    fn eq(&self, other: &(String, String)) -> bool {
+        self.key == other.0 && self.value == other.1
+    }
+}
+
+impl PartialEq<KeyValuePair> for (&str, &str) {
+    fn eq(&self, other: &KeyValuePair) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<KeyValuePair> for (&str, String) {
+    fn eq(&self, other: &KeyValuePair) -> bool {
+        other == self
+    }
+}
+
+impl PartialEq<KeyValuePair> for (String, String) {
+    fn eq(&self, other: &KeyValuePair) -> bool {
+        other == self
+    }
+}
+
+/// The value of a key-value pair, kept as its individual lines and joined with `\n` on demand.
+///
+/// See [LazyKeyValuePair].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MultiLineValue {
+    lines: Vec<String>,
+}
+
+impl MultiLineValue {
+    /// Create from the lines of a value.
+    pub fn new(lines: Vec<String>) -> Self {
+        Self { lines }
+    }
+
+    /// Get the lines of the value.
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Unwrap the lines of the value.
+    pub fn into_lines(self) -> Vec<String> {
+        self.lines
+    }
+
+    /// Join the lines of the value with `\n`.
+    pub fn join(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Join the lines of the value with `\n`, without copying if there is only a single line.
+    pub fn into_string(self) -> String {
+        if self.lines.len() == 1 {
+            self.lines.into_iter().next().unwrap_or_default()
+        } else {
+            self.join()
+        }
+    }
+}
+
+/// A key-value pair whose value is kept as separate lines, deferring joining multi-line values.
+///
+/// Returned by [KVParser::process_line_lazy](crate::KVParser::process_line_lazy).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LazyKeyValuePair {
+    pub key: String,
+    pub value: MultiLineValue,
+}
+
+impl LazyKeyValuePair {
+    /// Get the value, joining its lines with `\n`.
+    pub fn value(&self) -> String {
+        self.value.join()
+    }
+
+    /// Get the lines of the value.
+    pub fn value_lines(&self) -> &[String] {
+        self.value.lines()
+    }
+}
+
+impl From<LazyKeyValuePair> for KeyValuePair {
+    fn from(pair: LazyKeyValuePair) -> Self {
+        KeyValuePair {
+            key: pair.key,
+            value: pair.value.into_string(),
+        }
+    }
+}
+
+impl From<KeyValuePair> for LazyKeyValuePair {
+    fn from(pair: KeyValuePair) -> Self {
+        LazyKeyValuePair {
+            key: pair.key,
+            value: MultiLineValue::new(vec![pair.value]),
+        }
+    }
+}
+
+/// A key-value pair whose key is shared with other pairs having the same key.
+///
+/// Returned by [KVParser::process_line_interned](crate::KVParser::process_line_interned).
+#[derive(Debug, Clone, PartialEq)]
+pub struct InternedKeyValuePair {
+    pub key: std::sync::Arc<str>,
+    pub value: String,
+}
+
+impl From<InternedKeyValuePair> for KeyValuePair {
+    fn from(pair: InternedKeyValuePair) -> Self {
+        KeyValuePair {
+            key: pair.key.to_string(),
+            value: pair.value,
+        }
+    }
+}
+
 /// Implemented by things returned from parsing.
 pub trait ParserOutput {
     type Item;
@@ -99,10 +254,41 @@ impl ParserOutput for ParsedLine {
     }
 }
 
+/// An error found by a parser while processing a line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A line with no key, that was not part of a multi-line value, in strict mode.
+    ///
+    /// See [KVParser::strict](crate::KVParser::strict).
+    UnexpectedKeylessLine(String),
+    /// A multi-line value that was still in progress at the end of input,
+    /// with the lines of the value found so far.
+    ///
+    /// See [KVParser::finish](crate::KVParser::finish).
+    UnterminatedMultiline { key: String, value: String },
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseError::UnexpectedKeylessLine(line) => {
+                write!(f, "Unexpected line with no key: {:?}", line)
+            }
+            ParseError::UnterminatedMultiline { key, .. } => {
+                write!(f, "Multi-line value for key {:?} is not terminated", key)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
 /// The output of parsing a line of input, generally by some more sophisticated parser with state.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum Output<T> {
     /// The provided line was empty or whitespace-only.
+    #[default]
     EmptyLine,
     /// We are in the middle of some parse operation and are not ready to return a value yet
     Pending,
@@ -110,6 +296,10 @@ pub enum Output<T> {
     KeylessLine(String),
     /// The provided line completes a record
     Output(T),
+    /// The provided line could not be accepted
+    Error(ParseError),
+    /// The provided line is a comment, as determined by the parse policy
+    Comment(String),
 }
 
 impl<T> Output<T> {
@@ -130,13 +320,121 @@ impl<T> Output<T> {
             Output::Pending => Output::Pending,
             Output::KeylessLine(v) => Output::KeylessLine(v),
             Output::Output(v) => Output::Output(func(v)),
+            Output::Error(e) => Output::Error(e),
+            Output::Comment(v) => Output::Comment(v),
         }
     }
-}
 
-impl<T> Default for Output<T> {
-    fn default() -> Self {
-        Output::EmptyLine
+    /// Apply a function returning an [Output] to the contained value in the [Output::Output] variant,
+    /// passing all other variants through unchanged.
+    ///
+    /// Similar to `Option<T>::and_then()`: the function may fail by returning another variant,
+    /// such as [Output::KeylessLine].
+    pub fn and_then<U, F: FnOnce(T) -> Output<U>>(self, func: F) -> Output<U> {
+        match self {
+            Output::EmptyLine => Output::EmptyLine,
+            Output::Pending => Output::Pending,
+            Output::KeylessLine(v) => Output::KeylessLine(v),
+            Output::Output(v) => func(v),
+            Output::Error(e) => Output::Error(e),
+            Output::Comment(v) => Output::Comment(v),
+        }
+    }
+
+    /// Replace the [Output::Output] variant with [Output::EmptyLine] if the predicate
+    /// returns false for the contained value, passing all other variants through unchanged.
+    ///
+    /// Similar to `Option<T>::filter()`: a dropped value is treated like an empty line.
+    pub fn filter<F: FnOnce(&T) -> bool>(self, predicate: F) -> Output<T> {
+        match self {
+            Output::Output(v) if !predicate(&v) => Output::EmptyLine,
+            other => other,
+        }
+    }
+
+    /// Call a function with a reference to the contained value in the [Output::Output] variant,
+    /// then return `self` unchanged.
+    ///
+    /// Similar to `Option<T>::inspect()`: useful for logging in a chain of calls.
+    pub fn inspect<F: FnOnce(&T)>(self, func: F) -> Output<T> {
+        if let Output::Output(v) = &self {
+            func(v);
+        }
+        self
+    }
+
+    /// Combine with another output, for example from a parser processing a parallel stream in lock-step.
+    ///
+    /// Returns [Output::Output] with both values only if both are [Output::Output].
+    /// Otherwise, the variant of whichever is not [Output::Output] is kept,
+    /// and if neither is, the one coming first in this order is kept:
+    /// [Output::Error], [Output::KeylessLine], [Output::Pending], [Output::Comment],
+    /// then [Output::EmptyLine].
+    /// For example, if either is pending and neither has an error or keyless line, the result is pending.
+    /// Ties keep the variant from `self`.
+    pub fn zip<U>(self, other: Output<U>) -> Output<(T, U)> {
+        fn rank<V>(output: &Output<V>) -> u8 {
+            match output {
+                Output::Error(_) => 0,
+                Output::KeylessLine(_) => 1,
+                Output::Pending => 2,
+                Output::Comment(_) => 3,
+                Output::EmptyLine => 4,
+                Output::Output(_) => 5,
+            }
+        }
+        match (self, other) {
+            (Output::Output(t), Output::Output(u)) => Output::Output((t, u)),
+            (a, b) if rank(&a) <= rank(&b) => a.map(|_| unreachable!()),
+            (_, b) => b.map(|_| unreachable!()),
+        }
+    }
+
+    /// Returns `self` if it is [Output::Output], otherwise returns `other`.
+    ///
+    /// Similar to `Option<T>::or()`: all other variants of `self`, including
+    /// [Output::Pending] and [Output::KeylessLine], are replaced by `other`, whatever its variant.
+    /// This makes it suitable for a fallback chain like `strict.or(lenient)`.
+    pub fn or(self, other: Output<T>) -> Output<T> {
+        match self {
+            Output::Output(v) => Output::Output(v),
+            _ => other,
+        }
+    }
+
+    /// Returns the contained value in the [Output::Output] variant.
+    ///
+    /// Similar to `Option<T>::expect()`, this is mainly useful in tests and examples.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `msg` if the value is any other variant.
+    #[track_caller]
+    pub fn expect(self, msg: &str) -> T {
+        match self {
+            Output::Output(v) => v,
+            _ => panic!("{}", msg),
+        }
+    }
+
+    /// Apply a function to the contained line in the [Output::KeylessLine] variant,
+    /// passing all other variants through unchanged.
+    pub fn map_keyless<F: FnOnce(String) -> String>(self, func: F) -> Output<T> {
+        match self {
+            Output::KeylessLine(v) => Output::KeylessLine(func(v)),
+            other => other,
+        }
+    }
+
+    /// Apply `func` to the contained value in the [Output::Output] variant,
+    /// or `keyless_func` to the contained line in the [Output::KeylessLine] variant,
+    /// passing all other variants through unchanged.
+    pub fn map_or_keyless<U, F: FnOnce(T) -> U, G: FnOnce(String) -> String>(
+        self,
+        func: F,
+        keyless_func: G,
+    ) -> Output<U> {
+        self.map_keyless(keyless_func).map(func)
     }
 }
 
@@ -154,7 +452,7 @@ impl<T> ParserOutput for Output<T> {
     fn ok_or_err_on_keyless<E>(self, err: E) -> Result<Option<T>, E> {
         match self {
             Output::Output(v) => Ok(Some(v)),
-            Output::KeylessLine(_) => Err(err),
+            Output::KeylessLine(_) | Output::Error(_) => Err(err),
             _ => Ok(None),
         }
     }
@@ -162,7 +460,7 @@ impl<T> ParserOutput for Output<T> {
     fn ok_or_else_err_on_keyless<E, F: FnOnce() -> E>(self, err: F) -> Result<Option<T>, E> {
         match self {
             Output::Output(v) => Ok(Some(v)),
-            Output::KeylessLine(_) => Err(err()),
+            Output::KeylessLine(_) | Output::Error(_) => Err(err()),
             _ => Ok(None),
         }
     }
@@ -221,6 +519,53 @@ impl<T> LineNumber<T> {
     }
 }
 
+/// Allows calling methods of the value directly, such as `output.is_pending()`.
+///
+/// Methods of [LineNumber] itself, including its [ParserOutput] implementation
+/// and [LineNumber::map], take precedence over methods of the value with the same name.
+impl<T> core::ops::Deref for LineNumber<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+/// A flattened form of the output of [KVParser::process_line](crate::KVParser::process_line),
+/// with one variant per kind of output, each with its line number.
+///
+/// Easier to match exhaustively than `LineNumber<Output<KeyValuePair>>`,
+/// for example in state machines consuming the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseEvent {
+    /// A complete key: value pair.
+    Pair(LineNumber<KeyValuePair>),
+    /// A line with no key, that was not part of a multi-line value.
+    Keyless(LineNumber<String>),
+    /// An empty or whitespace-only line.
+    Empty(usize),
+    /// A line that started or continued a multi-line value.
+    MultilinePending(usize),
+    /// A comment line, as determined by the parse policy.
+    Comment(LineNumber<String>),
+    /// A line that could not be accepted.
+    Error(LineNumber<ParseError>),
+}
+
+impl From<LineNumber<Output<KeyValuePair>>> for ParseEvent {
+    fn from(output: LineNumber<Output<KeyValuePair>>) -> Self {
+        let (line, output) = output.into_tuple();
+        match output {
+            Output::EmptyLine => ParseEvent::Empty(line),
+            Output::Pending => ParseEvent::MultilinePending(line),
+            Output::KeylessLine(v) => ParseEvent::Keyless(LineNumber::new(line, v)),
+            Output::Output(pair) => ParseEvent::Pair(LineNumber::new(line, pair)),
+            Output::Error(e) => ParseEvent::Error(LineNumber::new(line, e)),
+            Output::Comment(v) => ParseEvent::Comment(LineNumber::new(line, v)),
+        }
+    }
+}
+
 impl<T: ParserOutput> ParserOutput for LineNumber<T> {
     type Item = T::Item;
 
@@ -239,3 +584,196 @@ impl<T: ParserOutput> ParserOutput for LineNumber<T> {
         self.value.ok_or_else_err_on_keyless(err)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{KeyValuePair, LineNumber, Output, ParseEvent, ParserOutput};
+
+    #[test]
+    fn parse_event() {
+        let pair = KeyValuePair {
+            key: "key".to_string(),
+            value: "value".to_string(),
+        };
+        let events: Vec<ParseEvent> = vec![
+            LineNumber::new(1, Output::Output(pair.clone())).into(),
+            LineNumber::new(2, Output::KeylessLine("line".to_string())).into(),
+            LineNumber::new(3, Output::EmptyLine).into(),
+            LineNumber::new(4, Output::Pending).into(),
+        ];
+        assert_eq!(
+            events,
+            vec![
+                ParseEvent::Pair(LineNumber::new(1, pair)),
+                ParseEvent::Keyless(LineNumber::new(2, "line".to_string())),
+                ParseEvent::Empty(3),
+                ParseEvent::MultilinePending(4),
+            ]
+        );
+    }
+
+    #[test]
+    fn pair_tuple_eq() {
+        let pair = KeyValuePair {
+            key: "key".to_string(),
+            value: "value".to_string(),
+        };
+        assert_eq!(pair, ("key", "value"));
+        assert_eq!(("key", "value"), pair);
+        assert_eq!(pair, ("key", "value".to_string()));
+        assert_eq!(("key", "value".to_string()), pair);
+        assert_eq!(pair, ("key".to_string(), "value".to_string()));
+        assert_eq!(("key".to_string(), "value".to_string()), pair);
+        assert_ne!(pair, ("key", "other"));
+        assert_ne!(pair, ("other", "value"));
+    }
+
+    #[test]
+    fn map_keyless() {
+        let keyless: Output<i32> = Output::KeylessLine(" header ".to_string());
+        assert_eq!(
+            keyless.clone().map_keyless(|v| v.trim().to_string()),
+            Output::KeylessLine("header".to_string())
+        );
+        assert_eq!(
+            keyless.map_or_keyless(|v| v + 1, |v| v.trim().to_string()),
+            Output::KeylessLine("header".to_string())
+        );
+        assert_eq!(
+            Output::Output(1).map_or_keyless(|v| v + 1, |v| v.trim().to_string()),
+            Output::Output(2)
+        );
+        assert_eq!(
+            Output::<i32>::Pending.map_keyless(|v| v.trim().to_string()),
+            Output::Pending
+        );
+    }
+
+    #[test]
+    fn and_then() {
+        fn parse(pair: KeyValuePair) -> Output<u32> {
+            match pair.value.parse() {
+                Ok(v) => Output::Output(v),
+                Err(_) => Output::KeylessLine(pair.value),
+            }
+        }
+        let pair = |value: &str| {
+            Output::Output(KeyValuePair {
+                key: "key".to_string(),
+                value: value.to_string(),
+            })
+        };
+        assert_eq!(pair("42").and_then(parse), Output::Output(42));
+        assert_eq!(
+            pair("x").and_then(parse),
+            Output::KeylessLine("x".to_string())
+        );
+        assert_eq!(Output::Pending.and_then(parse), Output::Pending);
+        assert_eq!(Output::EmptyLine.and_then(parse), Output::EmptyLine);
+    }
+
+    #[test]
+    fn or() {
+        let keyless = || Output::<i32>::KeylessLine("line".to_string());
+        assert_eq!(Output::Output(1).or(Output::Output(2)), Output::Output(1));
+        assert_eq!(Output::Output(1).or(keyless()), Output::Output(1));
+        assert_eq!(keyless().or(Output::Output(2)), Output::Output(2));
+        assert_eq!(Output::Pending.or(keyless()), keyless());
+        assert_eq!(keyless().or(Output::Pending), Output::Pending);
+        assert_eq!(
+            Output::<i32>::EmptyLine.or(Output::EmptyLine),
+            Output::EmptyLine
+        );
+    }
+
+    #[test]
+    fn split_value() {
+        let pair = |value: &str| KeyValuePair {
+            key: "Tags".to_string(),
+            value: value.to_string(),
+        };
+        assert_eq!(pair("a, b ,c").split_value(","), vec!["a", "b", "c"]);
+        assert_eq!(pair("a, b,").split_value(","), vec!["a", "b", ""]);
+        assert_eq!(pair("a").split_value(","), vec!["a"]);
+        assert!(pair("").split_value(",").is_empty());
+        assert!(pair("  ").split_value(",").is_empty());
+        assert_eq!(
+            pair("a, ,b,").split_value_trimmed_nonempty(","),
+            vec!["a", "b"]
+        );
+        assert!(pair(",").split_value_trimmed_nonempty(",").is_empty());
+    }
+
+    #[test]
+    fn line_number_deref() {
+        let output = LineNumber::new(3, Output::Output(1));
+        assert!(output.is_some());
+        assert!(!output.is_pending());
+        assert_eq!(output.line_number(), 3);
+        // The ParserOutput implementation of LineNumber is used, not that of Output.
+        assert_eq!(output.ok(), Some(1));
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(Output::Output(1).filter(|v| *v > 0), Output::Output(1));
+        assert_eq!(Output::Output(-1).filter(|v| *v > 0), Output::EmptyLine);
+        assert_eq!(
+            Output::<i32>::KeylessLine("line".to_string()).filter(|_| false),
+            Output::KeylessLine("line".to_string())
+        );
+        assert_eq!(Output::<i32>::Pending.filter(|_| false), Output::Pending);
+    }
+
+    #[test]
+    fn zip() {
+        let keyless = |line: &str| Output::<i32>::KeylessLine(line.to_string());
+        assert_eq!(
+            Output::Output(1).zip(Output::Output('a')),
+            Output::Output((1, 'a'))
+        );
+        assert_eq!(
+            Output::Output(1).zip(Output::<char>::EmptyLine),
+            Output::EmptyLine
+        );
+        assert_eq!(
+            Output::<i32>::EmptyLine.zip(Output::<char>::Pending),
+            Output::Pending
+        );
+        assert_eq!(
+            Output::<i32>::Comment("#".to_string()).zip(Output::<char>::Pending),
+            Output::Pending
+        );
+        assert_eq!(
+            keyless("a").zip(Output::<char>::Pending),
+            Output::KeylessLine("a".to_string())
+        );
+        assert_eq!(
+            keyless("a").zip(Output::<char>::KeylessLine("b".to_string())),
+            Output::KeylessLine("a".to_string())
+        );
+    }
+
+    #[test]
+    fn inspect() {
+        let mut seen = vec![];
+        let output = Output::Output(1).inspect(|v| seen.push(*v)).map(|v| v + 1);
+        assert_eq!(output, Output::Output(2));
+        assert_eq!(
+            Output::<i32>::Pending.inspect(|v| seen.push(*v)),
+            Output::Pending
+        );
+        assert_eq!(seen, vec![1]);
+    }
+
+    #[test]
+    fn expect() {
+        assert_eq!(Output::Output(1).expect("has a value"), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "line must have a pair")]
+    fn expect_panics() {
+        Output::<i32>::KeylessLine("line".to_string()).expect("line must have a pair");
+    }
+}